@@ -0,0 +1,470 @@
+use crate::config::{Config, ParseMode};
+use crate::daemon::Tasks;
+use crate::error::{Error, Result};
+use crate::http::Clients;
+use crate::notification::template::escape;
+use crate::notification::Registry;
+use crate::rss::{add_approved, get_client, rpc_call};
+use futures::FutureExt;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sled::{Db, IVec, Tree};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use transmission_rpc::types::Id;
+
+/// sled tree of the items of approval mode feeds, keyed by info-hash
+pub const APPROVALS_TREE: &str = "approvals";
+
+/// An item of an approval mode feed, kept until it's rejected or removed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Approval {
+    /// Title of the feed
+    pub feed: String,
+    pub title: String,
+    /// Torrent or magnet link of the feed item, fetched again once approved
+    pub link: String,
+    /// Page of the feed item, for the notifications
+    #[serde(default)]
+    pub page: Option<String>,
+    /// Key of the feed item in the seen items tree
+    pub key: Option<String>,
+    pub info_hash: String,
+    /// Secret of the callback links
+    pub token: String,
+    /// Approved and added to transmission
+    pub added: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Approve,
+    Reject,
+    /// Remove the torrent from transmission once added
+    Remove,
+}
+
+impl Action {
+    pub fn parse(name: &str) -> Option<Action> {
+        match name {
+            "approve" => Some(Action::Approve),
+            "reject" => Some(Action::Reject),
+            "remove" => Some(Action::Remove),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Approve => "approve",
+            Action::Reject => "reject",
+            Action::Remove => "remove",
+        }
+    }
+}
+
+/// Answer of an action for the user
+#[derive(Debug, Clone, PartialEq)]
+pub enum Answer {
+    Done(String),
+    /// The action doesn't apply to the item in its current state
+    Conflict(String),
+}
+
+impl std::fmt::Display for Answer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Answer::Done(text) | Answer::Conflict(text) => f.write_str(text),
+        }
+    }
+}
+
+impl Approval {
+    /// Random token for the callback links
+    pub fn new_token() -> String {
+        let bytes: [u8; 16] = rand::thread_rng().gen();
+        bytes.iter().map(|it| format!("{:02x}", it)).collect()
+    }
+
+    pub fn get(db: &Db, info_hash: &str) -> Result<Option<Approval>> {
+        let value = db.open_tree(APPROVALS_TREE)?.get(info_hash)?;
+        Ok(value.and_then(|it| serde_json::from_slice(&it).ok()))
+    }
+
+    pub fn save(&self, db: &Db) -> Result<()> {
+        db.open_tree(APPROVALS_TREE)?
+            .insert(&self.info_hash, self.to_vec())?;
+        Ok(())
+    }
+
+    fn to_vec(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("approvals always serialize")
+    }
+}
+
+/// Apply an action to the approval of `info_hash`, returns the answer for the user
+pub async fn handle(
+    db: &Arc<Db>,
+    cfg: &Config,
+    clients: &Clients,
+    notifiers: &Registry,
+    action: Action,
+    info_hash: &str,
+) -> Result<Answer> {
+    let tree = db.open_tree(APPROVALS_TREE)?;
+    let current = tree.get(info_hash)?;
+    let approval: Approval = match current
+        .as_ref()
+        .and_then(|it| serde_json::from_slice(it).ok())
+    {
+        Some(val) => val,
+        None => {
+            return Ok(Answer::Conflict(format!(
+                "Nothing to {} for {}",
+                action.name(),
+                info_hash
+            )))
+        }
+    };
+    let busy = || {
+        Ok(Answer::Conflict(format!(
+            "{} is already being handled",
+            approval.title
+        )))
+    };
+    match action {
+        Action::Approve if approval.added => Ok(Answer::Conflict(format!(
+            "{} was already approved",
+            approval.title
+        ))),
+        Action::Approve => {
+            // claimed before adding, so a double click can't add the torrent twice
+            let claimed: IVec = Approval {
+                added: true,
+                ..approval.clone()
+            }
+            .to_vec()
+            .into();
+            if !swap(&tree, info_hash, current.clone(), Some(claimed.clone()))? {
+                return busy();
+            }
+            let feed = cfg
+                .rss_list
+                .iter()
+                .find(|it| it.title == approval.feed)
+                .cloned();
+            if let Err(err) =
+                add_approved(db.clone(), &approval, feed, cfg.clone(), clients, notifiers).await
+            {
+                swap(&tree, info_hash, Some(claimed), current)?;
+                return Err(err);
+            }
+            Ok(Answer::Done(format!("Approved {}", approval.title)))
+        }
+        Action::Reject if approval.added => Ok(Answer::Conflict(format!(
+            "{} was already added, remove it instead",
+            approval.title
+        ))),
+        Action::Reject => {
+            if !swap(&tree, info_hash, current, None)? {
+                return busy();
+            }
+            Ok(Answer::Done(format!("Rejected {}", approval.title)))
+        }
+        Action::Remove if !approval.added => Ok(Answer::Conflict(format!(
+            "{} wasn't added, reject it instead",
+            approval.title
+        ))),
+        Action::Remove => {
+            if !swap(&tree, info_hash, current.clone(), None)? {
+                return busy();
+            }
+            let mut client = get_client(cfg)?;
+            let ids = vec![Id::Hash(approval.info_hash.clone())];
+            let rt = rpc_call(
+                cfg,
+                &cfg.retry,
+                &mut client,
+                |client| client.torrent_remove(ids.clone(), false).boxed(),
+                |result| Error::Transmission(result.into()),
+            )
+            .await;
+            if let Err(err) = rt {
+                swap(&tree, info_hash, None, current)?;
+                return Err(err);
+            }
+            // the info-hash stays in the db, so the item isn't added again
+            Ok(Answer::Done(format!("Removed {}", approval.title)))
+        }
+    }
+}
+
+/// Replace the stored approval if it's still `old`, false when another action changed it first,
+/// e.g. the same button pressed twice or in a chat and a callback link at once
+fn swap(tree: &Tree, info_hash: &str, old: Option<IVec>, new: Option<IVec>) -> Result<bool> {
+    Ok(tree.compare_and_swap(info_hash, old, new)?.is_ok())
+}
+
+/// Answer the approval links of the notifiers, `/approval/<action>/<info-hash>?token=<token>`.
+///
+/// `GET` only shows a page confirming the action with a `POST` to the same link, so link previews
+/// of chat apps can't approve or remove anything.
+pub async fn serve(
+    listener: TcpListener,
+    db: Arc<Db>,
+    cfg: Config,
+    clients: Clients,
+    notifiers: Arc<Registry>,
+    tasks: Arc<Tasks>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    log::info!(
+        "Approval callbacks listening on {:?}",
+        listener.local_addr().ok()
+    );
+    loop {
+        let stream = tokio::select! {
+            res = listener.accept() => res,
+            _ = shutdown.changed() => break,
+        };
+        let (stream, _) = match stream {
+            Ok(val) => val,
+            Err(err) => {
+                log::warn!("Failed to accept approval callback: {}", err);
+                continue;
+            }
+        };
+        let (db, cfg, clients, notifiers) =
+            (db.clone(), cfg.clone(), clients.clone(), notifiers.clone());
+        // tracked, so a shutdown doesn't stop an approval halfway through the add
+        tasks.spawn(async move {
+            if let Err(err) = callback(stream, &db, &cfg, &clients, &notifiers).await {
+                log::warn!("Approval callback failed: {}", err);
+            }
+        });
+    }
+}
+
+async fn callback(
+    mut stream: TcpStream,
+    db: &Arc<Db>,
+    cfg: &Config,
+    clients: &Clients,
+    notifiers: &Registry,
+) -> std::io::Result<()> {
+    // only the request line matters, it's within the first read for any sane client
+    let mut buf = vec![0; 4096];
+    let len = stream.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..len]);
+    let mut line = request.lines().next().unwrap_or_default().split(' ');
+    let method = line.next().unwrap_or_default();
+    let target = line.next().unwrap_or_default();
+
+    let (status, content_type, body) = match parse_target(target) {
+        None => ("404 Not Found", TEXT, "Not found".to_string()),
+        Some((action, info_hash, token)) => match Approval::get(db, info_hash) {
+            Ok(Some(approval)) if same_token(&approval.token, token) => match method {
+                "GET" => ("200 OK", HTML, confirmation(action, &approval)),
+                "POST" => match handle(db, cfg, clients, notifiers, action, info_hash).await {
+                    Ok(Answer::Done(val)) => ("200 OK", TEXT, val),
+                    Ok(Answer::Conflict(val)) => ("409 Conflict", TEXT, val),
+                    Err(err) => ("500 Internal Server Error", TEXT, err.to_string()),
+                },
+                _ => (
+                    "405 Method Not Allowed",
+                    TEXT,
+                    "Method not allowed".to_string(),
+                ),
+            },
+            Ok(_) => ("403 Forbidden", TEXT, "Unknown or expired link".to_string()),
+            Err(err) => ("500 Internal Server Error", TEXT, err.to_string()),
+        },
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+const TEXT: &str = "text/plain";
+const HTML: &str = "text/html";
+
+/// Page asking to confirm `action`, the form posts back to the link it was opened with
+fn confirmation(action: Action, approval: &Approval) -> String {
+    let title = escape(&approval.title, Some(ParseMode::Html));
+    let name = action.name();
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{name} {title}</title></head>\n\
+        <body><form method=\"post\"><p>{name} {title}?</p><button type=\"submit\">{name}</button></form></body></html>\n"
+    )
+}
+
+/// Constant time comparison of the tokens
+fn same_token(expected: &str, token: &str) -> bool {
+    expected.len() == token.len() && openssl::memcmp::eq(expected.as_bytes(), token.as_bytes())
+}
+
+/// (action, info-hash, token) of `/approval/<action>/<info-hash>?token=<token>`
+fn parse_target(target: &str) -> Option<(Action, &str, &str)> {
+    let (path, query) = target.split_once('?')?;
+    let mut parts = path.strip_prefix("/approval/")?.split('/');
+    let action = Action::parse(parts.next()?)?;
+    let info_hash = parts.next()?;
+    let token = query.split('&').find_map(|it| it.strip_prefix("token="))?;
+    Some((action, info_hash, token))
+}
+
+/// Link of the callback server for `action`
+pub fn callback_link(base_url: &str, action: Action, info_hash: &str, token: &str) -> String {
+    format!(
+        "{}/approval/{}/{}?token={}",
+        base_url.trim_end_matches('/'),
+        action.name(),
+        info_hash,
+        token
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_target() {
+        let link = callback_link("http://localhost:8091/", Action::Remove, "abc", "secret");
+        assert_eq!(
+            link,
+            "http://localhost:8091/approval/remove/abc?token=secret"
+        );
+        assert_eq!(
+            parse_target("/approval/remove/abc?token=secret"),
+            Some((Action::Remove, "abc", "secret"))
+        );
+        assert_eq!(parse_target("/approval/delete/abc?token=secret"), None);
+        assert_eq!(parse_target("/approval/remove/abc"), None);
+    }
+
+    #[tokio::test]
+    async fn test_callback() {
+        let db = Arc::new(sled::Config::new().temporary(true).open().unwrap());
+        let cfg: Config = toml::from_str(
+            r#"
+            [persistence]
+            path = "./test"
+            [transmission]
+            url = "http://127.0.0.1:1/transmission/rpc"
+            username = "user"
+            password = "pass"
+            [notification]
+            [[rss_list]]
+            title = "test"
+            url = "http://127.0.0.1:1/rss.xml"
+            filters = []
+            download_dir = "/downloads"
+            "#,
+        )
+        .unwrap();
+        let approval = Approval {
+            feed: "test".to_string(),
+            title: "Show <01>".to_string(),
+            link: "http://127.0.0.1:1/1.torrent".to_string(),
+            page: None,
+            key: None,
+            info_hash: "abc".to_string(),
+            token: "secret".to_string(),
+            added: false,
+        };
+        approval.save(&db).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let (_shutdown, shutdown_rx) = watch::channel(false);
+        tokio::spawn(serve(
+            listener,
+            db.clone(),
+            cfg,
+            Default::default(),
+            Arc::new(Registry::new()),
+            Default::default(),
+            shutdown_rx,
+        ));
+        let client = reqwest::Client::new();
+        let link = |action| callback_link(&base_url, action, "abc", "secret");
+
+        // opening the link changes nothing
+        let res = client.get(link(Action::Reject)).send().await.unwrap();
+        assert_eq!(res.status().as_u16(), 200);
+        let page = res.text().await.unwrap();
+        assert!(page.contains("<form method=\"post\">"), "{}", page);
+        assert!(page.contains("reject Show &lt;01&gt;?"), "{}", page);
+        assert!(Approval::get(&db, "abc").unwrap().is_some());
+
+        let res = client.post(link(Action::Remove)).send().await.unwrap();
+        assert_eq!(res.status().as_u16(), 409);
+        assert!(Approval::get(&db, "abc").unwrap().is_some());
+
+        let res = client.post(link(Action::Reject)).send().await.unwrap();
+        assert_eq!(res.status().as_u16(), 200);
+        assert_eq!(res.text().await.unwrap(), "Rejected Show <01>");
+        assert!(Approval::get(&db, "abc").unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_approve_once() {
+        let server = httpmock::MockServer::start_async().await;
+        let torrent = server
+            .mock_async(|when, then| {
+                when.path("/1.torrent");
+                then.status(404)
+                    .delay(std::time::Duration::from_millis(200));
+            })
+            .await;
+        let db = Arc::new(sled::Config::new().temporary(true).open().unwrap());
+        let cfg: Config = toml::from_str(
+            r#"
+            rss_list = []
+            [persistence]
+            path = "./test"
+            [transmission]
+            url = "http://127.0.0.1:1/transmission/rpc"
+            username = "user"
+            password = "pass"
+            [notification]
+            "#,
+        )
+        .unwrap();
+        let approval = Approval {
+            feed: "test".to_string(),
+            title: "Show - 01".to_string(),
+            link: server.url("/1.torrent"),
+            page: None,
+            key: None,
+            info_hash: "abc".to_string(),
+            token: "secret".to_string(),
+            added: false,
+        };
+        approval.save(&db).unwrap();
+        let (clients, notifiers) = (Clients::default(), Registry::new());
+
+        // a double click, the second one finds the item claimed by the first
+        let (first, second) = tokio::join!(
+            handle(&db, &cfg, &clients, &notifiers, Action::Approve, "abc"),
+            handle(&db, &cfg, &clients, &notifiers, Action::Approve, "abc"),
+        );
+        assert!(first.is_err());
+        assert_eq!(
+            second.unwrap(),
+            Answer::Conflict("Show - 01 was already approved".to_string())
+        );
+        torrent.assert_hits_async(1).await;
+        // the failed approval can be tried again
+        assert_eq!(Approval::get(&db, "abc").unwrap(), Some(approval));
+    }
+}
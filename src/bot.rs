@@ -0,0 +1,556 @@
+use crate::approval::{self, Action};
+use crate::config::Config;
+use crate::daemon::{Feeds, Tasks};
+use crate::error::Result;
+use crate::http::Clients;
+use crate::notification::telegram::{CallbackQuery, IncomingMessage, Telegram, Update};
+use crate::notification::Registry;
+use crate::rss::add_link;
+use sled::Db;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Seconds a `getUpdates` request waits for new messages
+const POLL_TIMEOUT: u64 = 30;
+
+const USAGE: &str = "Commands:
+/status - last run of every feed
+/run <feed> - poll a feed now
+/pause <feed> - stop polling a feed
+/resume <feed> - poll a paused feed again
+/add <url|magnet> [feed] - add a torrent, with the download dir of the feed if given";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Status,
+    Run(String),
+    Pause(String),
+    Resume(String),
+    Add { link: String, feed: Option<String> },
+    Help,
+}
+
+impl Command {
+    /// Parse a message, `None` when it isn't a command
+    pub fn parse(text: &str) -> Option<Command> {
+        let text = text.trim();
+        if !text.starts_with('/') {
+            return None;
+        }
+        let (name, arg) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        // commands are suffixed with the bot name in groups, e.g. /status@my_bot
+        let name = name.split('@').next().unwrap_or_default();
+        let arg = arg.trim().to_string();
+        let command = match name {
+            "/status" => Command::Status,
+            "/run" if !arg.is_empty() => Command::Run(arg),
+            "/pause" if !arg.is_empty() => Command::Pause(arg),
+            "/resume" if !arg.is_empty() => Command::Resume(arg),
+            "/add" if !arg.is_empty() => {
+                let (link, feed) = arg.split_once(char::is_whitespace).unwrap_or((&arg, ""));
+                let feed = feed.trim();
+                Command::Add {
+                    link: link.to_string(),
+                    feed: (!feed.is_empty()).then(|| feed.to_string()),
+                }
+            }
+            _ => Command::Help,
+        };
+        Some(command)
+    }
+}
+
+/// Telegram bot answering the commands sent from its chat in daemon mode
+pub struct Bot {
+    telegram: Arc<Telegram>,
+    feeds: Arc<Feeds>,
+    db: Arc<Db>,
+    cfg: Config,
+    clients: Clients,
+    notifiers: Arc<Registry>,
+    /// Torrents being added by `/add` or the approval buttons
+    tasks: Arc<Tasks>,
+}
+
+impl Bot {
+    pub fn new(
+        telegram: Telegram,
+        feeds: Arc<Feeds>,
+        db: Arc<Db>,
+        cfg: Config,
+        clients: Clients,
+        notifiers: Arc<Registry>,
+        tasks: Arc<Tasks>,
+    ) -> Self {
+        Bot {
+            telegram: Arc::new(telegram),
+            feeds,
+            db,
+            cfg,
+            clients,
+            notifiers,
+            tasks,
+        }
+    }
+
+    /// Answer commands until the shutdown signal
+    pub async fn run(self, mut shutdown: watch::Receiver<bool>) {
+        log::info!("Listening for telegram commands");
+        let mut offset = 0;
+        loop {
+            let updates = tokio::select! {
+                res = self.telegram.get_updates(offset, POLL_TIMEOUT) => res,
+                _ = shutdown.changed() => break,
+            };
+            match updates {
+                Ok(updates) => offset = self.handle_updates(updates, offset).await,
+                Err(err) => {
+                    log::warn!("Failed to get telegram updates: {}", err);
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+            }
+        }
+    }
+
+    /// Fetch and answer the pending commands once, returns the next offset
+    pub async fn poll(&self, offset: i64, timeout: u64) -> Result<i64> {
+        let updates = self.telegram.get_updates(offset, timeout).await?;
+        Ok(self.handle_updates(updates, offset).await)
+    }
+
+    async fn handle_updates(&self, updates: Vec<Update>, mut offset: i64) -> i64 {
+        for update in updates {
+            offset = offset.max(update.update_id + 1);
+            if let Some(query) = update.callback_query {
+                self.handle_callback(query);
+                continue;
+            }
+            let message = match update.message {
+                Some(val) => val,
+                None => continue,
+            };
+            if !self.telegram.is_target(message.chat.id) {
+                log::warn!("Ignoring message from chat {}", message.chat.id);
+                continue;
+            }
+            let command = match message.text.as_deref().and_then(Command::parse) {
+                Some(val) => val,
+                None => continue,
+            };
+            log::info!("Telegram command: {:?}", command);
+            let reply = self.handle(&message, command).await;
+            if let Err(err) = self.telegram.reply(&message, reply).await {
+                log::warn!("{}", err);
+            }
+        }
+        offset
+    }
+
+    /// Apply the approval button pressed under one of the bot's messages, in the background as
+    /// approving fetches the torrent and adds it
+    fn handle_callback(&self, query: CallbackQuery) {
+        let chat = query.message.as_ref().map(|it| it.chat.id);
+        if !chat.is_some_and(|it| self.telegram.is_target(it)) {
+            log::warn!("Ignoring button pressed in chat {:?}", chat);
+            return;
+        }
+        let (db, cfg, clients) = (self.db.clone(), self.cfg.clone(), self.clients.clone());
+        let (telegram, notifiers) = (self.telegram.clone(), self.notifiers.clone());
+        self.tasks.spawn(async move {
+            let answer = match query
+                .data
+                .as_deref()
+                .and_then(|it| it.split_once(':'))
+                .and_then(|(action, hash)| Some((Action::parse(action)?, hash)))
+            {
+                Some((action, hash)) => {
+                    log::info!("Telegram button: {} {}", action.name(), hash);
+                    match approval::handle(&db, &cfg, &clients, &notifiers, action, hash).await {
+                        Ok(val) => val.to_string(),
+                        Err(err) => format!("Failed to {}: {}", action.name(), err),
+                    }
+                }
+                None => "Unknown button".to_string(),
+            };
+            if let Err(err) = telegram.answer_callback_query(&query.id, &answer).await {
+                log::warn!("{}", err);
+            }
+        });
+    }
+
+    async fn handle(&self, message: &IncomingMessage, command: Command) -> String {
+        match command {
+            Command::Status => self.status(),
+            Command::Run(feed) => match self.feeds.get(&feed) {
+                Some(state) => {
+                    state.trigger();
+                    format!("Polling {} now", feed)
+                }
+                None => unknown_feed(&feed),
+            },
+            Command::Pause(feed) => match self.feeds.get(&feed) {
+                Some(state) => {
+                    state.pause();
+                    format!("Paused {}", feed)
+                }
+                None => unknown_feed(&feed),
+            },
+            Command::Resume(feed) => match self.feeds.get(&feed) {
+                Some(state) => {
+                    state.resume();
+                    format!("Resumed {}", feed)
+                }
+                None => unknown_feed(&feed),
+            },
+            Command::Add { link, feed } => {
+                let feed = match feed {
+                    Some(title) => match self.cfg.rss_list.iter().find(|it| it.title == title) {
+                        Some(val) => Some(val.clone()),
+                        None => return unknown_feed(&title),
+                    },
+                    None => None,
+                };
+                let reply = format!("Adding {}", link);
+                // fetching the torrent can take long, keep answering the other commands meanwhile
+                let (db, cfg, clients) = (self.db.clone(), self.cfg.clone(), self.clients.clone());
+                let (telegram, notifiers) = (self.telegram.clone(), self.notifiers.clone());
+                let message = message.clone();
+                self.tasks.spawn(async move {
+                    let reply = match add_link(db, link, feed, cfg, &clients, &notifiers).await {
+                        Ok(0) => "Already in transmission".to_string(),
+                        Ok(_) => "Added".to_string(),
+                        Err(err) => format!("Failed to add: {}", err),
+                    };
+                    if let Err(err) = telegram.reply(&message, reply).await {
+                        log::warn!("{}", err);
+                    }
+                });
+                reply
+            }
+            Command::Help => USAGE.to_string(),
+        }
+    }
+
+    fn status(&self) -> String {
+        let lines: Vec<_> = self
+            .cfg
+            .rss_list
+            .iter()
+            .map(|feed| {
+                let state = &self.feeds[&feed.title];
+                let paused = if state.is_paused() { " (paused)" } else { "" };
+                let last_run = match state.last_run() {
+                    None => "not polled yet".to_string(),
+                    Some(run) => {
+                        let ago = format_ago(run.at.elapsed());
+                        match run.result {
+                            Ok(count) => format!("{} added {}", count, ago),
+                            Err(err) => format!("failed {}: {}", ago, err),
+                        }
+                    }
+                };
+                format!("{}{}: {}", feed.title, paused, last_run)
+            })
+            .collect();
+        lines.join("\n")
+    }
+}
+
+fn unknown_feed(feed: &str) -> String {
+    format!("Unknown feed: {}", feed)
+}
+
+fn format_ago(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    match secs {
+        0..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m ago", secs / 60),
+        _ => format!("{}h {}m ago", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use httpmock::prelude::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Command::parse("hello"), None);
+        assert_eq!(Command::parse("/status@my_bot"), Some(Command::Status));
+        assert_eq!(
+            Command::parse("/run My List"),
+            Some(Command::Run("My List".to_string()))
+        );
+        assert_eq!(Command::parse("/pause"), Some(Command::Help));
+        assert_eq!(
+            Command::parse("/add magnet:?xt=urn:btih:abc My List"),
+            Some(Command::Add {
+                link: "magnet:?xt=urn:btih:abc".to_string(),
+                feed: Some("My List".to_string()),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_poll() {
+        let server = MockServer::start_async().await;
+        let updates = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path("/bottoken/getUpdates")
+                    .query_param("offset", "0");
+                then.status(200).json_body(json!({
+                    "ok": true,
+                    "result": [
+                        { "update_id": 7, "message": { "chat": { "id": 42 }, "text": "/pause anime" } },
+                        { "update_id": 8, "message": { "chat": { "id": 1 }, "text": "/pause linux" } },
+                        { "update_id": 9, "message": { "chat": { "id": 42 }, "text": "/status" } },
+                    ],
+                }));
+            })
+            .await;
+        let paused = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/bottoken/sendMessage")
+                    .json_body(json!({ "chat_id": 42, "text": "Paused anime" }));
+                then.status(200);
+            })
+            .await;
+        let status = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/bottoken/sendMessage")
+                    .json_body(json!({
+                        "chat_id": 42,
+                        "text": "anime (paused): not polled yet\nlinux: not polled yet",
+                    }));
+                then.status(200);
+            })
+            .await;
+
+        let cfg: Config = toml::from_str(
+            r#"
+[persistence]
+path = "test/bot"
+
+[transmission]
+url = "http://localhost/transmission/rpc"
+username = "user"
+password = "pass"
+
+[notification]
+
+[[rss_list]]
+title = "anime"
+url = "http://localhost/anime.xml"
+download_dir = "/downloads/anime"
+
+[[rss_list]]
+title = "linux"
+url = "http://localhost/linux.xml"
+download_dir = "/downloads/linux"
+"#,
+        )
+        .unwrap();
+        let feeds: Arc<Feeds> = Arc::new(
+            cfg.rss_list
+                .iter()
+                .map(|it| (it.title.clone(), Arc::default()))
+                .collect(),
+        );
+        let db = Arc::new(sled::Config::new().temporary(true).open().unwrap());
+        let telegram = Telegram::new("token".into(), 42, server.url(""));
+        let bot = Bot::new(
+            telegram,
+            feeds.clone(),
+            db,
+            cfg,
+            Default::default(),
+            Arc::new(Registry::new()),
+            Default::default(),
+        );
+
+        let offset = bot.poll(0, 0).await.unwrap();
+
+        assert_eq!(offset, 10);
+        updates.assert_async().await;
+        paused.assert_async().await;
+        status.assert_async().await;
+        assert!(feeds["anime"].is_paused());
+        // the message from another chat was ignored
+        assert!(!feeds["linux"].is_paused());
+    }
+
+    #[tokio::test]
+    async fn test_reject_button() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/bottoken/getUpdates");
+                then.status(200).json_body(json!({
+                    "ok": true,
+                    "result": [{
+                        "update_id": 3,
+                        "callback_query": {
+                            "id": "q1",
+                            "data": "reject:abc",
+                            "message": { "chat": { "id": 42 } },
+                        },
+                    }],
+                }));
+            })
+            .await;
+        let answer = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/bottoken/answerCallbackQuery")
+                    .json_body(json!({ "callback_query_id": "q1", "text": "Rejected Show - 01" }));
+                then.status(200);
+            })
+            .await;
+
+        let cfg: Config = toml::from_str(
+            r#"
+[persistence]
+path = "test/bot"
+
+[transmission]
+url = "http://localhost/transmission/rpc"
+username = "user"
+password = "pass"
+
+[notification]
+
+[[rss_list]]
+title = "anime"
+url = "http://localhost/anime.xml"
+download_dir = "/downloads/anime"
+approval = true
+"#,
+        )
+        .unwrap();
+        let db = Arc::new(sled::Config::new().temporary(true).open().unwrap());
+        approval::Approval {
+            feed: "anime".to_string(),
+            title: "Show - 01".to_string(),
+            link: "http://localhost/1.torrent".to_string(),
+            page: None,
+            key: None,
+            info_hash: "abc".to_string(),
+            token: "token".to_string(),
+            added: false,
+        }
+        .save(&db)
+        .unwrap();
+        let telegram = Telegram::new("token".into(), 42, server.url(""));
+        let tasks = Arc::new(Tasks::default());
+        let bot = Bot::new(
+            telegram,
+            Default::default(),
+            db.clone(),
+            cfg,
+            Default::default(),
+            Arc::new(Registry::new()),
+            tasks.clone(),
+        );
+
+        let offset = bot.poll(0, 0).await.unwrap();
+        tasks.wait().await;
+
+        assert_eq!(offset, 4);
+        answer.assert_async().await;
+        assert_eq!(approval::Approval::get(&db, "abc").unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_add_in_background() {
+        let server = MockServer::start_async().await;
+        let link = server.url("/1.torrent");
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/bottoken/getUpdates");
+                then.status(200).json_body(json!({
+                    "ok": true,
+                    "result": [
+                        { "update_id": 1, "message": { "chat": { "id": 42 }, "text": format!("/add {}", link) } },
+                        { "update_id": 2, "message": { "chat": { "id": 42 }, "text": "/status" } },
+                    ],
+                }));
+            })
+            .await;
+        let torrent = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/1.torrent");
+                then.status(404).delay(Duration::from_millis(500));
+            })
+            .await;
+        let adding = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/bottoken/sendMessage")
+                    .json_body(json!({ "chat_id": 42, "text": format!("Adding {}", link) }));
+                then.status(200);
+            })
+            .await;
+        let status = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/bottoken/sendMessage")
+                    .json_body(json!({ "chat_id": 42, "text": "" }));
+                then.status(200);
+            })
+            .await;
+        let failed = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/bottoken/sendMessage")
+                    .body_contains("Failed to add");
+                then.status(200);
+            })
+            .await;
+
+        let cfg: Config = toml::from_str(
+            r#"
+rss_list = []
+
+[persistence]
+path = "test/bot"
+
+[transmission]
+url = "http://localhost/transmission/rpc"
+username = "user"
+password = "pass"
+
+[notification]
+"#,
+        )
+        .unwrap();
+        let db = Arc::new(sled::Config::new().temporary(true).open().unwrap());
+        let telegram = Telegram::new("token".into(), 42, server.url(""));
+        let tasks = Arc::new(Tasks::default());
+        let bot = Bot::new(
+            telegram,
+            Default::default(),
+            db,
+            cfg,
+            Default::default(),
+            Arc::new(Registry::new()),
+            tasks.clone(),
+        );
+
+        let offset = bot.poll(0, 0).await.unwrap();
+
+        // the status was answered while the torrent was still being fetched
+        assert_eq!(offset, 3);
+        adding.assert_async().await;
+        status.assert_async().await;
+        assert_eq!(failed.hits_async().await, 0);
+        tasks.wait().await;
+        torrent.assert_async().await;
+        failed.assert_async().await;
+    }
+}
@@ -0,0 +1,608 @@
+use crate::config::{one_or_many, Config, Sources};
+use regex::Regex;
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::Url;
+use serde::de::{self, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use toml::Spanned;
+
+/// A problem found in the config, located in the file it comes from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.file, self.line, self.column, self.message
+        )
+    }
+}
+
+/// Read the config and the rules file and validate them, a file that can't be read is a problem
+/// at its first line
+pub fn check_files(config: &str, rules: Option<&str>) -> Vec<Problem> {
+    let mut files = Vec::new();
+    let mut problems = Vec::new();
+    for (name, path) in
+        std::iter::once(("config file", config)).chain(rules.map(|it| ("rules file", it)))
+    {
+        match std::fs::read_to_string(path) {
+            Ok(val) => files.push((path.to_string(), val)),
+            Err(err) => problems.push(Problem {
+                file: path.to_string(),
+                line: 1,
+                column: 1,
+                message: format!("{} {:?} can't be read: {}", name, path, err),
+            }),
+        }
+    }
+    if !files.is_empty() {
+        // the missing fields may well be in the file that couldn't be read
+        let complete = problems.is_empty();
+        problems.extend(check_sources(&Sources::new(files), complete));
+    }
+    problems
+}
+
+/// Validate the merged config without contacting any server, returning every problem found
+pub fn check_config(sources: &Sources) -> Vec<Problem> {
+    check_sources(sources, true)
+}
+
+/// Like [`check_config`], checking for missing fields only if the config is `complete`
+fn check_sources(sources: &Sources, complete: bool) -> Vec<Problem> {
+    let mut checker = Checker {
+        sources,
+        problems: Vec::new(),
+    };
+
+    // the document must at least be valid TOML with the expected types
+    let cfg: CheckedConfig = match toml::from_str(sources.merged()) {
+        Ok(val) => val,
+        Err(err) => {
+            checker.toml_error(&err);
+            return checker.problems;
+        }
+    };
+
+    if let Some(transmission) = &cfg.transmission {
+        if let Some(url) = &transmission.url {
+            checker.url("transmission url", url);
+        }
+        if let Some(path) = &transmission.password_file {
+            checker.file("password_file", path);
+        }
+    }
+
+    let mut titles: HashMap<&str, &Spanned<String>> = HashMap::new();
+    for feed in cfg.rss_list.iter() {
+        if let Some(title) = &feed.title {
+            match titles.get(title.get_ref().as_str()) {
+                Some(first) => {
+                    let (file, line, column) = sources.locate(first.start());
+                    let message = format!(
+                        "duplicate feed title {:?}, first defined at {}:{}:{}",
+                        title.get_ref(),
+                        file,
+                        line,
+                        column
+                    );
+                    checker.push(title.start(), message);
+                }
+                None => {
+                    titles.insert(title.get_ref(), title);
+                }
+            }
+        }
+        if let Some(url) = &feed.url {
+            checker.url("feed url", url);
+        }
+        if let Some(filters) = &feed.filters {
+            for pattern in filters.regex.iter() {
+                if let Err(err) = Regex::new(pattern.get_ref()) {
+                    checker.push(pattern.start(), format!("invalid filter regex: {}", err));
+                }
+            }
+        }
+        checker.headers(&feed.headers);
+        if let Some(value) = &feed.user_agent {
+            checker.header_value("user_agent", value);
+        }
+        if let Some(value) = &feed.cookies {
+            checker.header_value("cookies", value);
+        }
+        if let Some(path) = &feed.cookie_file {
+            checker.file("cookie_file", path);
+        }
+        if let Some(path) = &feed.ca_file {
+            checker.file("ca_file", path);
+        }
+        if let Some(proxy) = &feed.proxy {
+            if let Err(err) = reqwest::Proxy::all(proxy.get_ref()) {
+                checker.push(
+                    proxy.start(),
+                    format!("invalid proxy {:?}: {}", proxy.get_ref(), err),
+                );
+            }
+        }
+    }
+
+    if let Some(notification) = &cfg.notification {
+        for path in notification
+            .telegram
+            .iter()
+            .filter_map(|it| it.bot_token_file.as_ref())
+        {
+            checker.file("bot_token_file", path);
+        }
+        for path in notification
+            .feishu
+            .iter()
+            .filter_map(|it| it.webhook_file.as_ref())
+            .chain(
+                notification
+                    .discord
+                    .iter()
+                    .chain(notification.slack.iter())
+                    .filter_map(|it| it.webhook_file.as_ref()),
+            )
+        {
+            checker.file("webhook_file", path);
+        }
+        for path in notification
+            .feishu
+            .iter()
+            .filter_map(|it| it.secret_file.as_ref())
+        {
+            checker.file("secret_file", path);
+        }
+        for webhook in notification.webhook.iter() {
+            if let Some(path) = &webhook.url_file {
+                checker.file("url_file", path);
+            }
+            checker.headers(&webhook.headers);
+        }
+        for path in notification
+            .email
+            .iter()
+            .filter_map(|it| it.password_file.as_ref())
+        {
+            checker.file("password_file", path);
+        }
+        for path in notification
+            .ntfy
+            .iter()
+            .chain(notification.gotify.iter())
+            .filter_map(|it| it.token_file.as_ref())
+        {
+            checker.file("token_file", path);
+        }
+        for path in notification
+            .apprise
+            .iter()
+            .filter_map(|it| it.key_file.as_ref())
+        {
+            checker.file("key_file", path);
+        }
+    }
+
+    // missing fields and the like, a missing secret file would be reported twice
+    if complete && checker.problems.is_empty() {
+        if let Err(err) = toml::from_str::<Config>(sources.merged()) {
+            checker.toml_error(&err);
+        }
+    }
+    checker.problems
+}
+
+struct Checker<'a> {
+    sources: &'a Sources,
+    problems: Vec<Problem>,
+}
+
+impl Checker<'_> {
+    fn push(&mut self, offset: usize, message: String) {
+        let (file, line, column) = self.sources.locate(offset);
+        self.problems.push(Problem {
+            file: file.to_string(),
+            line,
+            column,
+            message,
+        });
+    }
+
+    fn toml_error(&mut self, err: &toml::de::Error) {
+        let offset = match err.line_col() {
+            Some((line, column)) => self.sources.offset_of(line, column),
+            None => 0,
+        };
+        self.push(offset, err.to_string());
+    }
+
+    fn url(&mut self, name: &str, url: &Spanned<String>) {
+        match Url::parse(url.get_ref()) {
+            Ok(val) if val.scheme() == "http" || val.scheme() == "https" => {}
+            Ok(val) => self.push(
+                url.start(),
+                format!("{} has unsupported scheme {:?}", name, val.scheme()),
+            ),
+            Err(err) => self.push(
+                url.start(),
+                format!("invalid {} {:?}: {}", name, url.get_ref(), err),
+            ),
+        }
+    }
+
+    fn headers(&mut self, headers: &BTreeMap<String, Spanned<String>>) {
+        for (name, value) in headers.iter() {
+            // keys have no span, the value stands in for them
+            if let Err(err) = HeaderName::from_bytes(name.as_bytes()) {
+                self.push(
+                    value.start(),
+                    format!("invalid header name {:?}: {}", name, err),
+                );
+            }
+            self.header_value(&format!("header {}", name), value);
+        }
+    }
+
+    fn header_value(&mut self, name: &str, value: &Spanned<String>) {
+        if let Err(err) = HeaderValue::from_str(value.get_ref()) {
+            self.push(
+                value.start(),
+                format!("invalid {} {:?}: {}", name, value.get_ref(), err),
+            );
+        }
+    }
+
+    fn file(&mut self, name: &str, path: &Spanned<String>) {
+        if let Err(err) = std::fs::read_to_string(path.get_ref()) {
+            self.push(
+                path.start(),
+                format!("{} {:?} can't be read: {}", name, path.get_ref(), err),
+            );
+        }
+    }
+}
+
+// Lenient mirror of `Config` keeping the position of the values that get checked,
+// toml only tracks spans of leaf values
+#[derive(Deserialize)]
+struct CheckedConfig {
+    transmission: Option<CheckedTransmission>,
+    #[serde(default)]
+    rss_list: Vec<CheckedFeed>,
+    notification: Option<CheckedNotification>,
+}
+
+#[derive(Deserialize)]
+struct CheckedTransmission {
+    url: Option<Spanned<String>>,
+    password_file: Option<Spanned<String>>,
+}
+
+#[derive(Deserialize)]
+struct CheckedFeed {
+    title: Option<Spanned<String>>,
+    url: Option<Spanned<String>>,
+    filters: Option<CheckedFilters>,
+    #[serde(default)]
+    headers: BTreeMap<String, Spanned<String>>,
+    user_agent: Option<Spanned<String>>,
+    cookies: Option<Spanned<String>>,
+    cookie_file: Option<Spanned<String>>,
+    ca_file: Option<Spanned<String>>,
+    proxy: Option<Spanned<String>>,
+}
+
+#[derive(Deserialize)]
+struct CheckedNotification {
+    #[serde(default, deserialize_with = "one_or_many")]
+    telegram: Vec<CheckedTelegram>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    feishu: Vec<CheckedFeishu>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    discord: Vec<CheckedWebhookFile>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    slack: Vec<CheckedWebhookFile>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    webhook: Vec<CheckedWebhook>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    email: Vec<CheckedEmail>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    ntfy: Vec<CheckedTokenFile>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    gotify: Vec<CheckedTokenFile>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    apprise: Vec<CheckedApprise>,
+}
+
+#[derive(Deserialize)]
+struct CheckedTelegram {
+    bot_token_file: Option<Spanned<String>>,
+}
+
+#[derive(Deserialize)]
+struct CheckedFeishu {
+    webhook_file: Option<Spanned<String>>,
+    secret_file: Option<Spanned<String>>,
+}
+
+// discord and slack
+#[derive(Deserialize)]
+struct CheckedWebhookFile {
+    webhook_file: Option<Spanned<String>>,
+}
+
+// ntfy and gotify
+#[derive(Deserialize)]
+struct CheckedTokenFile {
+    token_file: Option<Spanned<String>>,
+}
+
+#[derive(Deserialize)]
+struct CheckedApprise {
+    key_file: Option<Spanned<String>>,
+}
+
+#[derive(Deserialize)]
+struct CheckedEmail {
+    password_file: Option<Spanned<String>>,
+}
+
+#[derive(Deserialize)]
+struct CheckedWebhook {
+    url_file: Option<Spanned<String>>,
+    #[serde(default)]
+    headers: BTreeMap<String, Spanned<String>>,
+}
+
+/// Regex patterns of either form of `FilterRules`
+#[derive(Default)]
+struct CheckedFilters {
+    regex: Vec<Spanned<String>>,
+}
+
+impl<'de> Deserialize<'de> for CheckedFilters {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct FiltersVisitor;
+
+        impl<'de> Visitor<'de> for FiltersVisitor {
+            type Value = CheckedFilters;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a list of substrings or a table of filter rules")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                while seq.next_element::<String>()?.is_some() {}
+                Ok(CheckedFilters::default())
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut filters = CheckedFilters::default();
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "regex" | "exclude_regex" => {
+                            filters.regex.extend(map.next_value::<Vec<_>>()?)
+                        }
+                        "include" | "exclude" => _ = map.next_value::<Vec<String>>()?,
+                        "all" => _ = map.next_value::<Vec<Vec<String>>>()?,
+                        "case_insensitive" => _ = map.next_value::<bool>()?,
+                        _ => {
+                            map.next_value::<IgnoredAny>()?;
+                            return Err(de::Error::unknown_field(
+                                &key,
+                                &[
+                                    "include",
+                                    "exclude",
+                                    "regex",
+                                    "exclude_regex",
+                                    "all",
+                                    "case_insensitive",
+                                ],
+                            ));
+                        }
+                    }
+                }
+                Ok(filters)
+            }
+        }
+
+        deserializer.deserialize_any(FiltersVisitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONFIG: &str = r#"[persistence]
+path = "/tmp/db"
+
+[transmission]
+url = "http://localhost:9091/transmission/rpc"
+username = "user"
+password = "pass"
+
+[notification]
+"#;
+
+    fn check(rules: &str) -> Vec<Problem> {
+        let sources = Sources::new(vec![
+            ("config.toml".to_string(), CONFIG.to_string()),
+            ("rules.toml".to_string(), rules.to_string()),
+        ]);
+        check_config(&sources)
+    }
+
+    #[test]
+    fn test_valid() {
+        let problems = check(
+            r#"[[rss_list]]
+title = "a"
+url = "https://example.com/rss.xml"
+filters = ["1080p"]
+download_dir = "/downloads"
+headers = { Authorization = "passkey" }
+cookies = "uid=1; pass=abc"
+proxy = "socks5://127.0.0.1:1080"
+timeout = 30
+"#,
+        );
+        assert_eq!(problems, vec![]);
+    }
+
+    #[test]
+    fn test_problems() {
+        let problems = check(
+            r#"[[rss_list]]
+title = "a"
+url = "example.com/rss.xml"
+download_dir = "/downloads"
+
+[[rss_list]]
+title = "a"
+url = "https://example.com/rss.xml"
+download_dir = "/downloads"
+[rss_list.filters]
+regex = ["ok", "("]
+"#,
+        );
+        let problems: Vec<_> = problems
+            .iter()
+            .map(|it| (it.file.as_str(), it.line, it.column))
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                ("rules.toml", 3, 7),
+                ("rules.toml", 7, 9),
+                ("rules.toml", 11, 16)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_http_options() {
+        let problems = check(
+            r#"[[rss_list]]
+title = "a"
+url = "https://example.com/rss.xml"
+download_dir = "/downloads"
+headers = { "bad header" = "passkey", Authorization = "pass\nkey" }
+user_agent = "agent\u0007"
+cookies = "uid=1\r\n"
+"#,
+        );
+        let problems: Vec<_> = problems
+            .iter()
+            .map(|it| (it.line, it.column, it.message.as_str()))
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                (
+                    5,
+                    55,
+                    r#"invalid header Authorization "pass\nkey": failed to parse header value"#
+                ),
+                (
+                    5,
+                    28,
+                    r#"invalid header name "bad header": invalid HTTP header name"#
+                ),
+                (
+                    6,
+                    14,
+                    r#"invalid user_agent "agent\u{7}": failed to parse header value"#
+                ),
+                (
+                    7,
+                    11,
+                    r#"invalid cookies "uid=1\r\n": failed to parse header value"#
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let sources = Sources::new(vec![(
+            "config.toml".to_string(),
+            CONFIG.replace(
+                "password = \"pass\"",
+                "password_file = \"/nonexistent/password\"",
+            ),
+        )]);
+        let problems = check_config(&sources);
+        assert_eq!(problems.len(), 1);
+        assert_eq!((problems[0].line, problems[0].column), (7, 17));
+    }
+
+    #[test]
+    fn test_missing_rules_file() {
+        let dir = std::env::temp_dir().join(format!("check-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = dir.join("config.toml");
+        std::fs::write(&config, CONFIG).unwrap();
+        let rules = dir.join("rules.toml");
+
+        let problems = check_files(config.to_str().unwrap(), rules.to_str());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(
+            (
+                problems[0].file.as_str(),
+                problems[0].line,
+                problems[0].column
+            ),
+            (rules.to_str().unwrap(), 1, 1)
+        );
+        assert!(
+            problems[0].message.starts_with("rules file"),
+            "{}",
+            problems[0]
+        );
+    }
+
+    #[test]
+    fn test_zero_interval() {
+        let problems = check(
+            r#"[[rss_list]]
+title = "a"
+url = "https://example.com/rss.xml"
+download_dir = "/downloads"
+interval = 0
+"#,
+        );
+        assert_eq!(problems.len(), 1);
+        assert!(
+            problems[0]
+                .message
+                .contains("interval must be at least 1 second"),
+            "{}",
+            problems[0]
+        );
+    }
+
+    #[test]
+    fn test_syntax_error() {
+        let problems = check("[[rss_list]]\ntitle = \"a\nurl = \"\"\n");
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].file, "rules.toml");
+        assert_eq!(problems[0].line, 2);
+    }
+}
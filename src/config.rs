@@ -1,5 +1,87 @@
-use serde::{Deserialize, Serialize};
-use std::fs::read_to_string;
+use crate::error::{Error, Result};
+use crate::notification::EventKind;
+use crate::retry::RetryPolicy;
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::marker::PhantomData;
+
+/// Config files merged into one TOML document, the rules file is appended to the main config
+pub struct Sources {
+    /// (path, content) of every file in merge order
+    files: Vec<(String, String)>,
+    merged: String,
+    /// Byte offset of every file in `merged`
+    starts: Vec<usize>,
+}
+
+impl Sources {
+    pub fn new(files: Vec<(String, String)>) -> Self {
+        let mut merged = String::new();
+        let mut starts = Vec::new();
+        for (_, content) in files.iter() {
+            if !starts.is_empty() {
+                merged.push('\n');
+            }
+            starts.push(merged.len());
+            merged.push_str(content);
+        }
+        Sources {
+            files,
+            merged,
+            starts,
+        }
+    }
+
+    pub fn read(config: &str, rules: Option<&str>) -> Result<Self> {
+        let mut files = Vec::new();
+        for path in std::iter::once(config).chain(rules) {
+            files.push((path.to_string(), read_to_string(path)?));
+        }
+        Ok(Sources::new(files))
+    }
+
+    pub fn merged(&self) -> &str {
+        &self.merged
+    }
+
+    /// Map a byte offset in the merged document to (file, line, column), 1-based
+    pub fn locate(&self, offset: usize) -> (&str, usize, usize) {
+        let index = self
+            .starts
+            .iter()
+            .rposition(|start| *start <= offset)
+            .unwrap_or(0);
+        let (path, content) = &self.files[index];
+        let offset = (offset - self.starts[index]).min(content.len());
+        let before = &content[..offset];
+        let line = before.matches('\n').count() + 1;
+        let column = before
+            .rsplit('\n')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .count()
+            + 1;
+        (path, line, column)
+    }
+
+    /// Byte offset in the merged document of a 0-based (line, column), as reported by toml errors
+    pub fn offset_of(&self, line: usize, column: usize) -> usize {
+        let start: usize = self
+            .merged
+            .split_inclusive('\n')
+            .take(line)
+            .map(str::len)
+            .sum();
+        let rest = &self.merged[start.min(self.merged.len())..];
+        let column: usize = rest.chars().take(column).map(char::len_utf8).sum();
+        start + column
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Config {
@@ -7,10 +89,28 @@ pub struct Config {
     pub transmission: Transmission,
     pub rss_list: Vec<RssList>,
     pub notification: Notification,
+    #[serde(default)]
+    pub daemon: Daemon,
+    #[serde(default)]
+    pub concurrency: Concurrency,
+    /// Retries of the feed and .torrent requests and of the RPC calls
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    #[serde(default)]
+    pub quarantine: Quarantine,
+}
+
+impl Config {
+    /// Retry policy of `feed`, its own one if configured
+    pub fn retry_for<'a>(&'a self, feed: &'a RssList) -> &'a RetryPolicy {
+        feed.retry.as_ref().unwrap_or(&self.retry)
+    }
 }
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Persistence {
     pub path: String,
+    /// Directory keeping the last body of every feed, for debugging
+    pub feed_cache: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -22,13 +122,13 @@ pub struct Transmission {
 }
 
 impl TryFrom<RawTransmission> for Transmission {
-    type Error = std::io::Error;
+    type Error = Error;
 
     fn try_from(value: RawTransmission) -> Result<Self, Self::Error> {
         let password = match value.password {
             TransmissionPassword::Raw { password } => password,
             TransmissionPassword::File { password_file } => {
-                read_to_string(password_file)?.trim().to_string()
+                read_to_string(&password_file)?.trim().to_string()
             }
         };
         Ok(Transmission {
@@ -58,35 +158,368 @@ pub enum TransmissionPassword {
 pub struct RssList {
     pub title: String,
     pub url: String,
-    pub filters: Vec<String>,
+    #[serde(default)]
+    pub filters: FilterRules,
     pub download_dir: String,
+    #[serde(default)]
+    pub add_mode: AddMode,
+    /// Polling interval in seconds when running as a daemon, overrides `daemon.interval`
+    #[serde(default, deserialize_with = "optional_interval")]
+    pub interval: Option<u64>,
+    /// Ask for approval through the notifiers before adding matched items
+    #[serde(default)]
+    pub approval: bool,
+    /// Replaces the global `retry` policy for this feed
+    pub retry: Option<RetryPolicy>,
+    /// Used for both the feed and its .torrent files
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+/// Request settings of a feed, e.g. the session cookie of a private tracker
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[serde(try_from = "RawHttpOptions")]
+pub struct HttpOptions {
+    /// Extra request headers, e.g. a passkey
+    pub headers: BTreeMap<String, String>,
+    /// Value of the `Cookie` header, e.g. `uid=1; pass=abc`
+    pub cookies: Option<String>,
+    pub user_agent: Option<String>,
+    /// `http://`, `https://` or `socks5://` proxy url
+    pub proxy: Option<String>,
+    /// Timeout of a whole request in seconds
+    pub timeout: Option<u64>,
+    /// PEM file of an extra root certificate, for trackers with a private CA
+    pub ca_file: Option<String>,
+    /// Skip the certificate checks, only for self signed trackers
+    pub accept_invalid_certs: bool,
+}
+
+impl TryFrom<RawHttpOptions> for HttpOptions {
+    type Error = Error;
+
+    fn try_from(value: RawHttpOptions) -> Result<Self, Self::Error> {
+        let cookies = match value.cookies {
+            Some(FeedCookies::Raw { cookies }) => Some(cookies),
+            Some(FeedCookies::File { cookie_file }) => {
+                Some(read_to_string(&cookie_file)?.trim().to_string())
+            }
+            None => None,
+        };
+        Ok(HttpOptions {
+            headers: value.headers,
+            cookies,
+            user_agent: value.user_agent,
+            proxy: value.proxy,
+            timeout: value.timeout,
+            ca_file: value.ca_file,
+            accept_invalid_certs: value.accept_invalid_certs,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RawHttpOptions {
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(flatten)]
+    pub cookies: Option<FeedCookies>,
+    pub user_agent: Option<String>,
+    pub proxy: Option<String>,
+    pub timeout: Option<u64>,
+    pub ca_file: Option<String>,
+    #[serde(default)]
+    pub accept_invalid_certs: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum FeedCookies {
+    Raw { cookies: String },
+    File { cookie_file: String },
+}
+
+/// How torrents of a feed are submitted to transmission, magnet items are always added as magnet
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AddMode {
+    /// Upload the downloaded .torrent file, keeps the tracker list of private torrents
+    #[default]
+    Metainfo,
+    /// Rebuild a magnet link from the info-hash
+    Magnet,
+    /// Let transmission fetch the item link itself
+    Url,
+}
+
+/// Title filters of a feed, either a plain list of substrings or a table of rules
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(try_from = "RawFilterRules")]
+pub struct FilterRules {
+    /// Keep items containing any of these substrings
+    pub include: Vec<String>,
+    /// Drop items containing any of these substrings
+    pub exclude: Vec<String>,
+    /// Keep items matching any of these regular expressions
+    pub regex: Vec<String>,
+    /// Drop items matching any of these regular expressions
+    pub exclude_regex: Vec<String>,
+    /// Keep items containing every substring of at least one group
+    pub all: Vec<Vec<String>>,
+    pub case_insensitive: bool,
+}
+
+impl TryFrom<RawFilterRules> for FilterRules {
+    type Error = Error;
+
+    fn try_from(value: RawFilterRules) -> Result<Self, Self::Error> {
+        match value {
+            RawFilterRules::List(include) => Ok(FilterRules {
+                include,
+                ..FilterRules::default()
+            }),
+            RawFilterRules::Rules {
+                include,
+                exclude,
+                regex,
+                exclude_regex,
+                all,
+                case_insensitive,
+                unknown,
+            } => {
+                // a typo would silently match everything
+                if let Some(key) = unknown.keys().next() {
+                    return Err(Error::Config(serde::de::Error::custom(format!(
+                        "unknown filter rule {:?}, expected one of include, exclude, regex, \
+                        exclude_regex, all, case_insensitive",
+                        key
+                    ))));
+                }
+                Ok(FilterRules {
+                    include,
+                    exclude,
+                    regex,
+                    exclude_regex,
+                    all,
+                    case_insensitive,
+                })
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum RawFilterRules {
+    List(Vec<String>),
+    Rules {
+        #[serde(default)]
+        include: Vec<String>,
+        #[serde(default)]
+        exclude: Vec<String>,
+        #[serde(default)]
+        regex: Vec<String>,
+        #[serde(default)]
+        exclude_regex: Vec<String>,
+        #[serde(default)]
+        all: Vec<Vec<String>>,
+        #[serde(default)]
+        case_insensitive: bool,
+        /// Keys that aren't rules, rejected
+        #[serde(flatten)]
+        unknown: BTreeMap<String, toml::Value>,
+    },
+}
+
+// daemon mode scheduling
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Daemon {
+    /// Default polling interval in seconds
+    #[serde(default = "default_interval", deserialize_with = "interval")]
+    pub interval: u64,
+    /// Maximum random delay in seconds added to every interval
+    #[serde(default)]
+    pub jitter: u64,
+    /// Address of the approval callback server, e.g. `0.0.0.0:8091`
+    pub listen: Option<String>,
+}
+
+impl Default for Daemon {
+    fn default() -> Self {
+        Daemon {
+            interval: default_interval(),
+            jitter: 0,
+            listen: None,
+        }
+    }
+}
+
+fn default_interval() -> u64 {
+    30 * 60
+}
+
+/// Polling interval of at least a second, 0 would poll in a busy loop
+fn interval<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    match u64::deserialize(deserializer)? {
+        0 => Err(serde::de::Error::custom(
+            "interval must be at least 1 second",
+        )),
+        val => Ok(val),
+    }
+}
+
+fn optional_interval<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    interval(deserializer).map(Some)
+}
+
+// limits on parallel work, shared by every feed
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Concurrency {
+    /// Feeds processed at the same time
+    #[serde(default = "default_feeds")]
+    pub feeds: usize,
+    /// .torrent files fetched at the same time
+    #[serde(default = "default_torrents")]
+    pub torrents: usize,
+    /// Requests to a single host at the same time, feed and .torrent fetches alike
+    #[serde(default = "default_per_host")]
+    pub per_host: usize,
+}
+
+impl Default for Concurrency {
+    fn default() -> Self {
+        Concurrency {
+            feeds: default_feeds(),
+            torrents: default_torrents(),
+            per_host: default_per_host(),
+        }
+    }
 }
+
+fn default_feeds() -> usize {
+    4
+}
+
+fn default_torrents() -> usize {
+    8
+}
+
+fn default_per_host() -> usize {
+    2
+}
+
+// feed items whose torrent can't be fetched, tried again on later runs
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Quarantine {
+    /// Failed attempts of an item before it's quarantined and no longer tried
+    #[serde(default = "default_max_failures")]
+    pub max_failures: u32,
+    /// Seconds before a failed item is tried again, doubled after every further failure
+    #[serde(default = "default_failure_delay")]
+    pub base_delay: u64,
+    /// Upper bound of the delay in seconds
+    #[serde(default = "default_failure_max_delay")]
+    pub max_delay: u64,
+}
+
+impl Default for Quarantine {
+    fn default() -> Self {
+        Quarantine {
+            max_failures: default_max_failures(),
+            base_delay: default_failure_delay(),
+            max_delay: default_failure_max_delay(),
+        }
+    }
+}
+
+fn default_max_failures() -> u32 {
+    5
+}
+
+fn default_failure_delay() -> u64 {
+    10 * 60
+}
+
+fn default_failure_max_delay() -> u64 {
+    24 * 60 * 60
+}
+
+/// Every backend section can be a single table or an array of tables for several instances
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Notification {
-    pub telegram: Option<TelegramNotification>,
-    pub feishu: Option<FeishuNotification>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub telegram: Vec<TelegramNotification>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub feishu: Vec<FeishuNotification>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub discord: Vec<DiscordNotification>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub slack: Vec<SlackNotification>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub webhook: Vec<WebhookNotification>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub email: Vec<EmailNotification>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub ntfy: Vec<NtfyNotification>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub gotify: Vec<GotifyNotification>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub apprise: Vec<AppriseNotification>,
+    /// Sections of backends unknown to this crate, see `notification::Registry::from_config_with`
+    #[serde(flatten)]
+    pub other: HashMap<String, toml::Value>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(try_from = "RawTelegramNotification")]
 pub struct TelegramNotification {
     pub bot_token: String,
-    pub chat_id: i64,
+    /// Chats receiving the events, `chat_id` is the first one
+    pub targets: Vec<TelegramTarget>,
+    /// Chats receiving the events of a feed instead of `targets`, by feed title
+    pub feeds: HashMap<String, Vec<TelegramTarget>>,
+    /// Message template of every event kind, see `notification::template`
+    pub templates: HashMap<EventKind, String>,
+    /// Markup of the templates, plain text without it
+    pub parse_mode: Option<ParseMode>,
+    /// Accept bot commands from the target chats in daemon mode. The approval buttons are only
+    /// attached along with it, as the bot answering them runs only then.
+    pub commands: bool,
+    pub events: Vec<EventKind>,
 }
 
 impl TryFrom<RawTelegramNotification> for TelegramNotification {
-    type Error = std::io::Error;
+    type Error = Error;
 
     fn try_from(value: RawTelegramNotification) -> Result<Self, Self::Error> {
         let bot_token = match value.bot_token {
             TelegramToken::Raw { bot_token } => bot_token,
             TelegramToken::File { bot_token_file } => {
-                read_to_string(bot_token_file)?.trim().to_string()
+                read_to_string(&bot_token_file)?.trim().to_string()
             }
         };
+        let targets: Vec<_> = value
+            .chat_id
+            .map(|chat_id| TelegramTarget {
+                chat_id,
+                ..Default::default()
+            })
+            .into_iter()
+            .chain(value.targets)
+            .collect();
+        if targets.is_empty() {
+            return Err(Error::Config(serde::de::Error::custom(
+                "telegram needs a chat_id or targets",
+            )));
+        }
         Ok(TelegramNotification {
             bot_token,
-            chat_id: value.chat_id,
+            targets,
+            feeds: value.feeds,
+            templates: by_event(value.templates)?,
+            parse_mode: value.parse_mode,
+            commands: value.commands,
+            events: value.events,
         })
     }
 }
@@ -95,7 +528,18 @@ impl TryFrom<RawTelegramNotification> for TelegramNotification {
 pub struct RawTelegramNotification {
     #[serde(flatten)]
     pub bot_token: TelegramToken,
-    pub chat_id: i64,
+    pub chat_id: Option<i64>,
+    #[serde(default)]
+    pub targets: Vec<TelegramTarget>,
+    #[serde(default)]
+    pub feeds: HashMap<String, Vec<TelegramTarget>>,
+    #[serde(default)]
+    pub templates: HashMap<String, String>,
+    pub parse_mode: Option<ParseMode>,
+    #[serde(default)]
+    pub commands: bool,
+    #[serde(default = "default_events")]
+    pub events: Vec<EventKind>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -105,24 +549,68 @@ pub enum TelegramToken {
     File { bot_token_file: String },
 }
 
+/// A telegram chat, or a topic of a forum supergroup
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TelegramTarget {
+    pub chat_id: i64,
+    /// Topic of a forum supergroup
+    pub message_thread_id: Option<i64>,
+    /// Deliver silently, without a sound
+    #[serde(default)]
+    pub disable_notification: bool,
+    /// Markup of the templates in this chat, overrides the one of the notifier
+    pub parse_mode: Option<ParseMode>,
+}
+
+/// Telegram formatting options, the template variables are escaped accordingly
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ParseMode {
+    MarkdownV2,
+    #[serde(rename = "HTML")]
+    Html,
+}
+
 // feishu webhook notification
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(try_from = "RawFeishuNotification")]
 pub struct FeishuNotification {
     pub webhook: String,
+    /// Message template of every event kind, see `notification::template`
+    pub templates: HashMap<EventKind, String>,
+    /// Send interactive cards instead of plain text, the templates are rendered as lark_md
+    pub card: bool,
+    /// Public url of the approval callback server, enables the approval buttons
+    pub callback_url: Option<String>,
+    /// Signing secret of bots with signature verification enabled
+    pub secret: Option<String>,
+    pub events: Vec<EventKind>,
 }
 
 impl TryFrom<RawFeishuNotification> for FeishuNotification {
-    type Error = std::io::Error;
+    type Error = Error;
 
     fn try_from(value: RawFeishuNotification) -> Result<Self, Self::Error> {
         let webhook = match value.webhook {
             FeishuWebhook::Raw { webhook } => webhook,
             FeishuWebhook::File { webhook_file } => {
-                read_to_string(webhook_file)?.trim().to_string()
+                read_to_string(&webhook_file)?.trim().to_string()
             }
         };
-        Ok(FeishuNotification { webhook })
+        let secret = match value.secret {
+            Some(FeishuSecret::Raw { secret }) => Some(secret),
+            Some(FeishuSecret::File { secret_file }) => {
+                Some(read_to_string(&secret_file)?.trim().to_string())
+            }
+            None => None,
+        };
+        Ok(FeishuNotification {
+            webhook,
+            templates: by_event(value.templates)?,
+            card: value.card,
+            callback_url: value.callback_url,
+            secret,
+            events: value.events,
+        })
     }
 }
 
@@ -130,6 +618,15 @@ impl TryFrom<RawFeishuNotification> for FeishuNotification {
 pub struct RawFeishuNotification {
     #[serde(flatten)]
     pub webhook: FeishuWebhook,
+    #[serde(default)]
+    pub templates: HashMap<String, String>,
+    #[serde(default)]
+    pub card: bool,
+    pub callback_url: Option<String>,
+    #[serde(flatten)]
+    pub secret: Option<FeishuSecret>,
+    #[serde(default = "default_events")]
+    pub events: Vec<EventKind>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -138,3 +635,441 @@ pub enum FeishuWebhook {
     Raw { webhook: String },
     File { webhook_file: String },
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum FeishuSecret {
+    Raw { secret: String },
+    File { secret_file: String },
+}
+
+// discord webhook notification
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "RawDiscordNotification")]
+pub struct DiscordNotification {
+    pub webhook: String,
+    pub events: Vec<EventKind>,
+}
+
+impl TryFrom<RawDiscordNotification> for DiscordNotification {
+    type Error = Error;
+
+    fn try_from(value: RawDiscordNotification) -> Result<Self, Self::Error> {
+        let webhook = match value.webhook {
+            DiscordWebhook::Raw { webhook } => webhook,
+            DiscordWebhook::File { webhook_file } => {
+                read_to_string(&webhook_file)?.trim().to_string()
+            }
+        };
+        Ok(DiscordNotification {
+            webhook,
+            events: value.events,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RawDiscordNotification {
+    #[serde(flatten)]
+    pub webhook: DiscordWebhook,
+    #[serde(default = "default_events")]
+    pub events: Vec<EventKind>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum DiscordWebhook {
+    Raw { webhook: String },
+    File { webhook_file: String },
+}
+
+// slack incoming webhook notification
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "RawSlackNotification")]
+pub struct SlackNotification {
+    pub webhook: String,
+    pub events: Vec<EventKind>,
+}
+
+impl TryFrom<RawSlackNotification> for SlackNotification {
+    type Error = Error;
+
+    fn try_from(value: RawSlackNotification) -> Result<Self, Self::Error> {
+        let webhook = match value.webhook {
+            SlackWebhook::Raw { webhook } => webhook,
+            SlackWebhook::File { webhook_file } => {
+                read_to_string(&webhook_file)?.trim().to_string()
+            }
+        };
+        Ok(SlackNotification {
+            webhook,
+            events: value.events,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RawSlackNotification {
+    #[serde(flatten)]
+    pub webhook: SlackWebhook,
+    #[serde(default = "default_events")]
+    pub events: Vec<EventKind>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum SlackWebhook {
+    Raw { webhook: String },
+    File { webhook_file: String },
+}
+
+/// Generic webhook, the event is POSTed as the JSON rendered from `body`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "RawWebhookNotification")]
+pub struct WebhookNotification {
+    pub url: String,
+    /// Body template, the placeholders of the event are replaced with the JSON escaped values
+    pub body: String,
+    /// Extra request headers, e.g. for authentication
+    pub headers: HashMap<String, String>,
+    pub events: Vec<EventKind>,
+}
+
+impl TryFrom<RawWebhookNotification> for WebhookNotification {
+    type Error = Error;
+
+    fn try_from(value: RawWebhookNotification) -> Result<Self, Self::Error> {
+        let url = match value.url {
+            WebhookUrl::Raw { url } => url,
+            WebhookUrl::File { url_file } => read_to_string(&url_file)?.trim().to_string(),
+        };
+        Ok(WebhookNotification {
+            url,
+            body: value.body,
+            headers: value.headers,
+            events: value.events,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RawWebhookNotification {
+    #[serde(flatten)]
+    pub url: WebhookUrl,
+    #[serde(default = "default_webhook_body")]
+    pub body: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default = "default_events")]
+    pub events: Vec<EventKind>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum WebhookUrl {
+    Raw { url: String },
+    File { url_file: String },
+}
+
+fn default_webhook_body() -> String {
+    r#"{"event": "{event}", "message": "{message}"}"#.to_string()
+}
+
+// smtp notification
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "RawEmailNotification")]
+pub struct EmailNotification {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    /// Send the `added` events of a run as a single email
+    pub digest: bool,
+    pub events: Vec<EventKind>,
+}
+
+impl TryFrom<RawEmailNotification> for EmailNotification {
+    type Error = Error;
+
+    fn try_from(value: RawEmailNotification) -> Result<Self, Self::Error> {
+        let password = match value.password {
+            Some(EmailPassword::Raw { password }) => Some(password),
+            Some(EmailPassword::File { password_file }) => {
+                Some(read_to_string(&password_file)?.trim().to_string())
+            }
+            None => None,
+        };
+        Ok(EmailNotification {
+            host: value.host,
+            port: value.port.unwrap_or(match value.tls {
+                SmtpTls::Starttls => 587,
+                SmtpTls::Implicit => 465,
+                SmtpTls::None => 25,
+            }),
+            tls: value.tls,
+            username: value.username,
+            password,
+            from: value.from,
+            to: value.to,
+            digest: value.digest,
+            events: value.events,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RawEmailNotification {
+    pub host: String,
+    /// Defaults to the usual port of `tls`
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    #[serde(flatten)]
+    pub password: Option<EmailPassword>,
+    pub from: String,
+    pub to: Vec<String>,
+    #[serde(default)]
+    pub digest: bool,
+    #[serde(default = "default_events")]
+    pub events: Vec<EventKind>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum EmailPassword {
+    Raw { password: String },
+    File { password_file: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Upgrade a plain connection with STARTTLS
+    #[default]
+    Starttls,
+    /// TLS from the start, also known as SMTPS
+    Implicit,
+    /// No encryption, only for servers on a trusted network
+    None,
+}
+
+/// Priority of a push notification, mapped to the scale of every backend
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Min,
+    Low,
+    #[default]
+    Default,
+    High,
+    Max,
+}
+
+/// Priority of every event kind, failures are high unless configured otherwise
+fn priorities(configured: HashMap<String, Priority>) -> Result<HashMap<EventKind, Priority>> {
+    let mut priority = HashMap::from([
+        (EventKind::FeedFailed, Priority::High),
+        (EventKind::TransmissionUnreachable, Priority::High),
+    ]);
+    priority.extend(by_event(configured)?);
+    Ok(priority)
+}
+
+/// Parse the event kind keys of a table, toml can't deserialize enum keys itself
+fn by_event<T>(configured: HashMap<String, T>) -> Result<HashMap<EventKind, T>> {
+    let mut values = HashMap::new();
+    for (kind, value) in configured {
+        values.insert(toml::Value::String(kind).try_into()?, value);
+    }
+    Ok(values)
+}
+
+// ntfy notification
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "RawNtfyNotification")]
+pub struct NtfyNotification {
+    pub server: String,
+    pub topic: String,
+    /// Access token of protected topics
+    pub token: Option<String>,
+    pub priority: HashMap<EventKind, Priority>,
+    pub events: Vec<EventKind>,
+}
+
+impl TryFrom<RawNtfyNotification> for NtfyNotification {
+    type Error = Error;
+
+    fn try_from(value: RawNtfyNotification) -> Result<Self, Self::Error> {
+        let token = match value.token {
+            Some(PushToken::Raw { token }) => Some(token),
+            Some(PushToken::File { token_file }) => {
+                Some(read_to_string(&token_file)?.trim().to_string())
+            }
+            None => None,
+        };
+        Ok(NtfyNotification {
+            server: value.server,
+            topic: value.topic,
+            token,
+            priority: priorities(value.priority)?,
+            events: value.events,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RawNtfyNotification {
+    #[serde(default = "default_ntfy_server")]
+    pub server: String,
+    pub topic: String,
+    #[serde(flatten)]
+    pub token: Option<PushToken>,
+    #[serde(default)]
+    pub priority: HashMap<String, Priority>,
+    #[serde(default = "default_events")]
+    pub events: Vec<EventKind>,
+}
+
+fn default_ntfy_server() -> String {
+    "https://ntfy.sh".to_string()
+}
+
+// gotify notification
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "RawGotifyNotification")]
+pub struct GotifyNotification {
+    pub server: String,
+    /// Application token
+    pub token: String,
+    pub priority: HashMap<EventKind, Priority>,
+    pub events: Vec<EventKind>,
+}
+
+impl TryFrom<RawGotifyNotification> for GotifyNotification {
+    type Error = Error;
+
+    fn try_from(value: RawGotifyNotification) -> Result<Self, Self::Error> {
+        let token = match value.token {
+            PushToken::Raw { token } => token,
+            PushToken::File { token_file } => read_to_string(&token_file)?.trim().to_string(),
+        };
+        Ok(GotifyNotification {
+            server: value.server,
+            token,
+            priority: priorities(value.priority)?,
+            events: value.events,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RawGotifyNotification {
+    pub server: String,
+    #[serde(flatten)]
+    pub token: PushToken,
+    #[serde(default)]
+    pub priority: HashMap<String, Priority>,
+    #[serde(default = "default_events")]
+    pub events: Vec<EventKind>,
+}
+
+// apprise-api notification
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "RawAppriseNotification")]
+pub struct AppriseNotification {
+    pub server: String,
+    /// Key of the configuration stored on the apprise server
+    pub key: String,
+    pub priority: HashMap<EventKind, Priority>,
+    pub events: Vec<EventKind>,
+}
+
+impl TryFrom<RawAppriseNotification> for AppriseNotification {
+    type Error = Error;
+
+    fn try_from(value: RawAppriseNotification) -> Result<Self, Self::Error> {
+        let key = match value.key {
+            AppriseKey::Raw { key } => key,
+            AppriseKey::File { key_file } => read_to_string(&key_file)?.trim().to_string(),
+        };
+        Ok(AppriseNotification {
+            server: value.server,
+            key,
+            priority: priorities(value.priority)?,
+            events: value.events,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RawAppriseNotification {
+    pub server: String,
+    #[serde(flatten)]
+    pub key: AppriseKey,
+    #[serde(default)]
+    pub priority: HashMap<String, Priority>,
+    #[serde(default = "default_events")]
+    pub events: Vec<EventKind>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum PushToken {
+    Raw { token: String },
+    File { token_file: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum AppriseKey {
+    Raw { key: String },
+    File { key_file: String },
+}
+
+/// Deserialize either a single value or a list of them
+pub(crate) fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    struct OneOrMany<T>(PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de>> Visitor<'de> for OneOrMany<T> {
+        type Value = Vec<T>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a table or an array of tables")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+            Ok(vec![T::deserialize(MapAccessDeserializer::new(map))?])
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+            Vec::deserialize(SeqAccessDeserializer::new(seq))
+        }
+    }
+
+    deserializer.deserialize_any(OneOrMany(PhantomData))
+}
+
+/// Events a notifier subscribes to when `events` isn't set, the run summary is opt-in
+pub fn default_events() -> Vec<EventKind> {
+    vec![
+        EventKind::Added,
+        EventKind::ApprovalRequested,
+        EventKind::FeedFailed,
+        EventKind::TransmissionUnreachable,
+    ]
+}
+
+fn read_to_string(path: &str) -> Result<String> {
+    fs::read_to_string(path).map_err(|source| Error::Io {
+        path: path.to_string(),
+        source,
+    })
+}
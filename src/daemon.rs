@@ -0,0 +1,353 @@
+use crate::approval;
+use crate::bot::Bot;
+use crate::config::{Config, RssList};
+use crate::error::{Error, Result};
+use crate::http::Clients;
+use crate::limit::Limits;
+use crate::notification::telegram::{self, Telegram};
+use crate::notification::{Event, FeedSummary, Registry};
+use crate::rss::process_feed;
+use rand::Rng;
+use sled::Db;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{watch, Notify};
+use tokio::task::JoinSet;
+
+/// Run state of a feed, shared with the bot commands
+#[derive(Default)]
+pub struct FeedState {
+    paused: AtomicBool,
+    run_now: Notify,
+    last_run: Mutex<Option<LastRun>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LastRun {
+    pub at: Instant,
+    /// Torrents added, or the error that stopped the feed
+    pub result: std::result::Result<i32, String>,
+}
+
+/// State of every feed by title
+pub type Feeds = HashMap<String, Arc<FeedState>>;
+
+impl FeedState {
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Skip the scheduled polls until resumed
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    /// Poll right away, even when paused
+    pub fn trigger(&self) {
+        self.run_now.notify_one();
+    }
+
+    pub fn last_run(&self) -> Option<LastRun> {
+        self.last_run.lock().unwrap().clone()
+    }
+
+    fn record(&self, result: std::result::Result<i32, String>) {
+        *self.last_run.lock().unwrap() = Some(LastRun {
+            at: Instant::now(),
+            result,
+        });
+    }
+}
+
+/// Work started in the background by the bot and the approval callbacks, awaited on shutdown like
+/// the feeds
+#[derive(Default)]
+pub struct Tasks(Mutex<JoinSet<()>>);
+
+impl Tasks {
+    pub fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) {
+        let mut tasks = self.0.lock().unwrap();
+        // reap the finished tasks, so the set doesn't grow for the lifetime of the daemon
+        while let Some(res) = tasks.try_join_next() {
+            if let Err(err) = res {
+                log::error!("Background task panicked: {}", err);
+            }
+        }
+        tasks.spawn(task);
+    }
+
+    /// Wait for every task spawned so far
+    pub async fn wait(&self) {
+        let mut tasks = std::mem::take(&mut *self.0.lock().unwrap());
+        while let Some(res) = tasks.join_next().await {
+            if let Err(err) = res {
+                log::error!("Background task panicked: {}", err);
+            }
+        }
+    }
+}
+
+/// Poll every feed on its own interval until SIGTERM/SIGINT is received.
+///
+/// In-flight feeds and the torrents being added or approved are allowed to finish before the db is
+/// flushed and the function returns.
+pub async fn run(db: Arc<Db>, cfg: Config, notifiers: Arc<Registry>) -> Result<()> {
+    // bind before anything is spawned, so a taken port fails the startup
+    let listener = match &cfg.daemon.listen {
+        Some(addr) => match tokio::net::TcpListener::bind(addr).await {
+            Ok(val) => Some(val),
+            Err(source) => {
+                return Err(Error::Io {
+                    path: addr.clone(),
+                    source,
+                })
+            }
+        },
+        None => None,
+    };
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let limits = Arc::new(Limits::new(&cfg.concurrency));
+    let clients = Clients::default();
+    let tasks = Arc::new(Tasks::default());
+    let feeds: Arc<Feeds> = Arc::new(
+        cfg.rss_list
+            .iter()
+            .map(|feed| (feed.title.clone(), Arc::default()))
+            .collect(),
+    );
+
+    let mut handles: Vec<_> = cfg
+        .rss_list
+        .iter()
+        .cloned()
+        .map(|feed| {
+            tokio::spawn(poll_feed(
+                db.clone(),
+                feeds[&feed.title].clone(),
+                feed,
+                cfg.clone(),
+                clients.clone(),
+                limits.clone(),
+                notifiers.clone(),
+                shutdown_rx.clone(),
+            ))
+        })
+        .collect();
+    log::info!("Daemon started with {} feeds", handles.len());
+
+    for bot_cfg in cfg.notification.telegram.iter().filter(|it| it.commands) {
+        let telegram = Telegram::from_config(bot_cfg, telegram::API.into());
+        let bot = Bot::new(
+            telegram,
+            feeds.clone(),
+            db.clone(),
+            cfg.clone(),
+            clients.clone(),
+            notifiers.clone(),
+            tasks.clone(),
+        );
+        handles.push(tokio::spawn(bot.run(shutdown_rx.clone())));
+    }
+
+    handles.push(tokio::spawn(cycle(
+        feeds.clone(),
+        cfg.rss_list.iter().map(|it| it.title.clone()).collect(),
+        notifiers.clone(),
+        cfg.daemon.interval,
+        shutdown_rx.clone(),
+    )));
+
+    if let Some(listener) = listener {
+        handles.push(tokio::spawn(approval::serve(
+            listener,
+            db.clone(),
+            cfg.clone(),
+            clients.clone(),
+            notifiers.clone(),
+            tasks.clone(),
+            shutdown_rx.clone(),
+        )));
+    }
+
+    wait_for_signal().await;
+    log::info!("Shutdown requested, waiting for in-flight feeds");
+    let _ = shutdown_tx.send(true);
+
+    for handle in handles {
+        if let Err(err) = handle.await {
+            log::error!("Daemon task panicked: {}", err);
+        }
+    }
+    // the bot and the callbacks are stopped, nothing is spawned anymore
+    tasks.wait().await;
+    db.flush_async().await?;
+    log::info!("Daemon stopped");
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn poll_feed(
+    db: Arc<Db>,
+    state: Arc<FeedState>,
+    feed: RssList,
+    cfg: Config,
+    clients: Clients,
+    limits: Arc<Limits>,
+    notifiers: Arc<Registry>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let interval = feed.interval.unwrap_or(cfg.daemon.interval);
+    let mut triggered = false;
+    loop {
+        if *shutdown.borrow() {
+            break;
+        }
+        if state.is_paused() && !triggered {
+            log::debug!("[{:?}] paused, skipping poll", feed.title);
+        } else {
+            // not raced against the shutdown signal, so a running feed always completes
+            let rt = process_feed(
+                db.clone(),
+                feed.clone(),
+                cfg.clone(),
+                &clients,
+                limits.clone(),
+                notifiers.clone(),
+            )
+            .await;
+            if let Err(err) = &rt {
+                log::error!("Failed to process {} feed: {}", feed.title, err);
+                notifiers
+                    .notify_all(&Event::failure(&feed.title, err))
+                    .await;
+            }
+            state.record(rt.map_err(|err| err.to_string()));
+            notifiers.flush().await;
+        }
+
+        let delay = next_delay(interval, cfg.daemon.jitter);
+        log::debug!("[{:?}] next poll in {:?}", feed.title, delay);
+        triggered = false;
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = state.run_now.notified() => triggered = true,
+            _ = shutdown.changed() => break,
+        }
+    }
+}
+
+/// Every `interval` seconds, send one summary of the feeds polled meanwhile, like the one ending a
+/// run, and the notifications left in the outbox. Done once for all the feeds, so the summary
+/// isn't split by feed and an outbox entry isn't sent twice.
+async fn cycle(
+    feeds: Arc<Feeds>,
+    titles: Vec<String>,
+    notifiers: Arc<Registry>,
+    interval: u64,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut since = Instant::now();
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(interval)) => {}
+            _ = shutdown.changed() => break,
+        }
+        let summary = summary(&feeds, &titles, since);
+        since = Instant::now();
+        if !summary.is_empty() {
+            notifiers
+                .notify_all(&Event::Summary { feeds: summary })
+                .await;
+            notifiers.flush().await;
+        }
+        // the outbox was retried on startup already
+        if let Err(err) = notifiers.retry_outbox().await {
+            log::warn!("Failed to retry the notification outbox: {}", err);
+        }
+    }
+}
+
+/// Last result of the feeds polled after `since`, in the order of the config
+fn summary(feeds: &Feeds, titles: &[String], since: Instant) -> Vec<FeedSummary> {
+    titles
+        .iter()
+        .filter_map(|title| {
+            let run = feeds.get(title)?.last_run()?;
+            (run.at >= since).then(|| FeedSummary {
+                feed: title.clone(),
+                result: run.result,
+            })
+        })
+        .collect()
+}
+
+fn next_delay(interval: u64, jitter: u64) -> Duration {
+    let jitter = if jitter > 0 {
+        rand::thread_rng().gen_range(0..=jitter)
+    } else {
+        0
+    };
+    Duration::from_secs(interval + jitter)
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut term = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+    tokio::select! {
+        _ = term.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let feeds: Feeds = ["anime", "linux", "news"]
+            .iter()
+            .map(|it| (it.to_string(), Arc::default()))
+            .collect();
+        let titles: Vec<_> = ["anime", "linux", "news"]
+            .iter()
+            .map(|it| it.to_string())
+            .collect();
+        feeds["linux"].record(Ok(1));
+        let since = Instant::now();
+        feeds["news"].record(Err("timeout".to_string()));
+        feeds["anime"].record(Ok(2));
+
+        let summary = summary(&feeds, &titles, since);
+        let polled: Vec<_> = summary
+            .iter()
+            .map(|it| (it.feed.as_str(), &it.result))
+            .collect();
+        assert_eq!(
+            polled,
+            vec![("anime", &Ok(2)), ("news", &Err("timeout".to_string()))]
+        );
+    }
+
+    #[test]
+    fn test_next_delay() {
+        assert_eq!(next_delay(60, 0), Duration::from_secs(60));
+        for _ in 0..100 {
+            let delay = next_delay(60, 10);
+            assert!(delay >= Duration::from_secs(60) && delay <= Duration::from_secs(70));
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use lava_torrent::LavaTorrentError;
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Errors of the config, feed, transmission and notification paths
+#[derive(Debug)]
+pub enum Error {
+    /// Reading a config, rules or secret file failed
+    Io { path: String, source: io::Error },
+    /// The merged config doesn't match the expected format
+    Config(toml::de::Error),
+    /// A url of the config can't be parsed
+    InvalidUrl {
+        url: String,
+        source: url::ParseError,
+    },
+    /// The HTTP options of a feed can't be applied, e.g. an invalid header or proxy
+    HttpOptions(Box<dyn StdError + Send + Sync>),
+    /// A filter regex doesn't compile
+    Filter(regex::Error),
+    /// Fetching a feed failed, including non success statuses
+    FeedFetch { url: String, source: reqwest::Error },
+    /// The feed body isn't a valid feed
+    FeedParse {
+        url: String,
+        source: crate::feed::ParseError,
+    },
+    /// Fetching a .torrent file failed, including non success statuses
+    TorrentFetch { url: String, source: reqwest::Error },
+    /// The .torrent file can't be parsed
+    InvalidTorrent {
+        url: String,
+        source: LavaTorrentError,
+    },
+    /// The magnet link has no usable info-hash
+    InvalidMagnet { url: String },
+    /// Transmission rejected the configured credentials
+    TransmissionAuth { url: String },
+    /// A transmission RPC call failed
+    Transmission(Box<dyn StdError + Send + Sync>),
+    /// Transmission answered `torrent-add` with an error result
+    TorrentAdd { title: String, result: String },
+    /// The sled db couldn't be opened, read or written
+    Db(sled::Error),
+    /// The existing db can't be opened for a dry run, usually locked by a running daemon
+    DbLocked { path: String, source: sled::Error },
+    /// A notification couldn't be delivered
+    Notification {
+        backend: String,
+        source: Box<dyn StdError + Send + Sync>,
+    },
+    /// The notification backend answered with an error
+    NotificationRejected { backend: String, message: String },
+    /// The notification backend asked to slow down
+    NotificationRateLimited {
+        backend: String,
+        retry_after: Option<std::time::Duration>,
+    },
+}
+
+impl Error {
+    /// Whether transmission itself failed rather than a feed
+    pub fn is_transmission(&self) -> bool {
+        matches!(
+            self,
+            Error::Transmission(_) | Error::TransmissionAuth { .. }
+        )
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "Failed to read {:?}: {}", path, source),
+            Error::Config(source) => write!(f, "Invalid config: {}", source),
+            Error::InvalidUrl { url, source } => write!(f, "Invalid url {:?}: {}", url, source),
+            Error::HttpOptions(source) => write!(f, "Invalid HTTP options: {}", source),
+            Error::Filter(source) => write!(f, "Invalid filter: {}", source),
+            Error::FeedFetch { url, source } => {
+                write!(f, "Failed to fetch the feed {:?}: {}", url, source)
+            }
+            Error::FeedParse { url, source } => {
+                write!(f, "Failed to parse the feed {:?}: {}", url, source)
+            }
+            Error::TorrentFetch { url, source } => {
+                write!(f, "Failed to fetch the torrent file {:?}: {}", url, source)
+            }
+            Error::InvalidTorrent { url, source } => {
+                write!(f, "Invalid torrent file {:?}: {}", url, source)
+            }
+            Error::InvalidMagnet { url } => write!(f, "Invalid magnet link: {:?}", url),
+            Error::TransmissionAuth { url } => {
+                write!(f, "Transmission at {:?} rejected the credentials", url)
+            }
+            Error::Transmission(source) => write!(f, "Transmission RPC failed: {}", source),
+            Error::TorrentAdd { title, result } => {
+                write!(f, "Failed to add torrent {}: {}", title, result)
+            }
+            Error::Db(source) => write!(f, "Database error: {}", source),
+            Error::DbLocked { path, source } => write!(
+                f,
+                "The database {:?} can't be opened, stop the running instance for a dry run: {}",
+                path, source
+            ),
+            Error::Notification { backend, source } => {
+                write!(f, "Failed to send {} notification: {}", backend, source)
+            }
+            Error::NotificationRejected { backend, message } => {
+                write!(f, "{} rejected the notification: {}", backend, message)
+            }
+            Error::NotificationRateLimited {
+                backend,
+                retry_after,
+            } => match retry_after {
+                Some(val) => write!(f, "{} is rate limited, retry after {:?}", backend, val),
+                None => write!(f, "{} is rate limited", backend),
+            },
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Config(source) => Some(source),
+            Error::InvalidUrl { source, .. } => Some(source),
+            Error::HttpOptions(source) => Some(source.as_ref()),
+            Error::Filter(source) => Some(source),
+            Error::FeedFetch { source, .. } => Some(source),
+            Error::FeedParse { source, .. } => Some(source),
+            Error::TorrentFetch { source, .. } => Some(source),
+            Error::InvalidTorrent { source, .. } => Some(source),
+            Error::Transmission(source) => Some(source.as_ref()),
+            Error::Db(source) => Some(source),
+            Error::DbLocked { source, .. } => Some(source),
+            Error::Notification { source, .. } => Some(source.as_ref()),
+            Error::InvalidMagnet { .. }
+            | Error::TransmissionAuth { .. }
+            | Error::TorrentAdd { .. }
+            | Error::NotificationRejected { .. }
+            | Error::NotificationRateLimited { .. } => None,
+        }
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(value: toml::de::Error) -> Self {
+        Error::Config(value)
+    }
+}
+
+impl From<regex::Error> for Error {
+    fn from(value: regex::Error) -> Self {
+        Error::Filter(value)
+    }
+}
+
+impl From<sled::Error> for Error {
+    fn from(value: sled::Error) -> Self {
+        Error::Db(value)
+    }
+}
@@ -0,0 +1,182 @@
+//! Feed items whose torrent couldn't be fetched. They're tried again on later runs with a growing
+//! delay, and quarantined once they failed too often until cleared from the command line.
+
+use crate::config::{Quarantine, RssList};
+use crate::error::Result;
+use crate::rss::FEEDS_TREE;
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// sled tree of the failed feed items, keyed by item key
+pub const FAILURES_TREE: &str = "failures";
+
+/// Failed attempts of a feed item, dropped once its torrent is fetched
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Failure {
+    /// Key of the feed item in the seen items tree
+    pub key: String,
+    /// Title of the feed
+    pub feed: String,
+    pub feed_url: String,
+    pub title: String,
+    pub link: String,
+    pub attempts: u32,
+    pub last_error: String,
+    /// Unix time of the last attempt in seconds
+    pub last_attempt: u64,
+    /// No longer tried until cleared
+    pub quarantined: bool,
+}
+
+impl Failure {
+    /// An item without any failed attempt yet
+    pub fn new(key: String, feed: &RssList, title: String, link: String) -> Failure {
+        Failure {
+            key,
+            feed: feed.title.clone(),
+            feed_url: feed.url.clone(),
+            title,
+            link,
+            attempts: 0,
+            last_error: String::new(),
+            last_attempt: 0,
+            quarantined: false,
+        }
+    }
+
+    pub fn get(db: &Db, key: &str) -> Result<Option<Failure>> {
+        let value = db.open_tree(FAILURES_TREE)?.get(key)?;
+        Ok(value.and_then(|it| serde_json::from_slice(&it).ok()))
+    }
+
+    pub fn save(&self, db: &Db) -> Result<()> {
+        let value = serde_json::to_vec(self).expect("failures always serialize");
+        db.open_tree(FAILURES_TREE)?.insert(&self.key, value)?;
+        Ok(())
+    }
+
+    pub fn delete(db: &Db, key: &str) -> Result<()> {
+        db.open_tree(FAILURES_TREE)?.remove(key)?;
+        Ok(())
+    }
+
+    /// Every recorded failure, quarantined or not
+    pub fn list(db: &Db) -> Result<Vec<Failure>> {
+        let mut failures = Vec::new();
+        for entry in db.open_tree(FAILURES_TREE)?.iter() {
+            let (_, value) = entry?;
+            if let Ok(val) = serde_json::from_slice(&value) {
+                failures.push(val);
+            }
+        }
+        Ok(failures)
+    }
+
+    /// Count another failed attempt of this item, quarantining it after `cfg.max_failures`
+    pub fn record(&mut self, cfg: &Quarantine, error: String) {
+        self.attempts += 1;
+        self.last_error = error;
+        self.last_attempt = now();
+        self.quarantined = self.attempts >= cfg.max_failures;
+    }
+
+    /// Whether the delay after the last attempt is over
+    pub fn is_due(&self, cfg: &Quarantine) -> bool {
+        let delay = Duration::from_secs(cfg.base_delay)
+            .saturating_mul(2u32.saturating_pow(self.attempts.saturating_sub(1)))
+            .min(Duration::from_secs(cfg.max_delay));
+        now() >= self.last_attempt.saturating_add(delay.as_secs())
+    }
+}
+
+/// Release the quarantined item `key`, or every quarantined item, returns how many were released.
+///
+/// The feeds are fetched in full on their next run, a not modified feed would skip the items.
+pub fn clear(db: &Db, key: Option<&str>) -> Result<usize> {
+    let released: Vec<_> = Failure::list(db)?
+        .into_iter()
+        .filter(|it| {
+            it.quarantined
+                && match key {
+                    Some(key) => key == it.key,
+                    None => true,
+                }
+        })
+        .collect();
+    let feeds = db.open_tree(FEEDS_TREE)?;
+    for failure in released.iter() {
+        Failure::delete(db, &failure.key)?;
+        feeds.remove(&failure.feed_url)?;
+    }
+    db.flush()?;
+    Ok(released.len())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |it| it.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failure(key: &str) -> Failure {
+        Failure {
+            key: key.to_string(),
+            feed: "test".to_string(),
+            feed_url: "https://example.com/rss.xml".to_string(),
+            title: "Show - 01".to_string(),
+            link: "https://example.com/1.torrent".to_string(),
+            attempts: 0,
+            last_error: String::new(),
+            last_attempt: 0,
+            quarantined: false,
+        }
+    }
+
+    #[test]
+    fn test_record() {
+        let cfg = Quarantine {
+            max_failures: 2,
+            ..Default::default()
+        };
+        let mut it = failure("a");
+        assert!(it.is_due(&cfg));
+
+        it.record(&cfg, "404 Not Found".to_string());
+        assert!(!it.quarantined);
+        assert!(!it.is_due(&cfg));
+        it.last_attempt -= cfg.base_delay;
+        assert!(it.is_due(&cfg));
+
+        it.record(&cfg, "404 Not Found".to_string());
+        assert!(it.quarantined);
+        assert_eq!(it.attempts, 2);
+    }
+
+    #[test]
+    fn test_clear() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let mut quarantined = failure("a");
+        quarantined.quarantined = true;
+        quarantined.save(&db).unwrap();
+        let mut other = quarantined.clone();
+        other.key = "b".to_string();
+        other.save(&db).unwrap();
+        failure("c").save(&db).unwrap();
+        db.open_tree(FEEDS_TREE)
+            .unwrap()
+            .insert(&quarantined.feed_url, b"{}")
+            .unwrap();
+
+        assert_eq!(clear(&db, Some("a")).unwrap(), 1);
+        assert!(Failure::get(&db, "a").unwrap().is_none());
+        assert!(db.open_tree(FEEDS_TREE).unwrap().is_empty());
+        // the failing but not quarantined item is kept
+        assert_eq!(clear(&db, None).unwrap(), 1);
+        assert_eq!(Failure::list(&db).unwrap(), vec![failure("c")]);
+    }
+}
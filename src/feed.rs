@@ -0,0 +1,338 @@
+//! Feed formats, RSS 2.0, Atom 1.0 and JSON Feed bodies are sniffed and read into [`FeedItem`]s
+
+use serde::Deserialize;
+use std::error::Error as StdError;
+use std::fmt;
+
+/// An item of any feed format
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FeedItem {
+    pub title: String,
+    pub link: Option<String>,
+    pub enclosure: Option<Enclosure>,
+    /// Unique id of the item, RSS `guid`, Atom `id` or JSON Feed `id`
+    pub guid: Option<String>,
+    /// Publication date as found in the feed, RFC 2822 for RSS and RFC 3339 otherwise
+    pub pub_date: Option<String>,
+}
+
+/// Attached file, RSS `enclosure`, Atom `enclosure` link or JSON Feed attachment
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Enclosure {
+    pub url: String,
+    pub mime_type: String,
+    /// Size in bytes
+    pub length: Option<u64>,
+}
+
+/// Mime type of .torrent files, preferred when an item has several enclosures
+pub const BITTORRENT: &str = "application/x-bittorrent";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FeedFormat {
+    Rss,
+    Atom,
+    Json,
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    Rss(rss::Error),
+    Atom(atom_syndication::Error),
+    Json(serde_json::Error),
+    /// The body isn't any of the supported formats
+    Unknown,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Rss(source) => write!(f, "invalid RSS: {}", source),
+            ParseError::Atom(source) => write!(f, "invalid Atom: {}", source),
+            ParseError::Json(source) => write!(f, "invalid JSON Feed: {}", source),
+            ParseError::Unknown => write!(f, "not an RSS, Atom or JSON feed"),
+        }
+    }
+}
+
+impl StdError for ParseError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            ParseError::Rss(source) => Some(source),
+            ParseError::Atom(source) => Some(source),
+            ParseError::Json(source) => Some(source),
+            ParseError::Unknown => None,
+        }
+    }
+}
+
+/// Guess the format from the root element, or the opening brace of JSON
+pub fn sniff(content: &[u8]) -> Option<FeedFormat> {
+    let text = String::from_utf8_lossy(content);
+    let mut rest = text.trim_start_matches('\u{feff}').trim_start();
+    if rest.starts_with('{') {
+        return Some(FeedFormat::Json);
+    }
+    // skip the xml declaration, comments, doctype and processing instructions
+    loop {
+        rest = rest.trim_start();
+        if rest.starts_with("<!--") {
+            rest = &rest[rest.find("-->")? + 3..];
+        } else if rest.starts_with("<?") || rest.starts_with("<!") {
+            rest = &rest[rest.find('>')? + 1..];
+        } else {
+            break;
+        }
+    }
+    let name = rest.strip_prefix('<')?;
+    let name = &name[..name.find(|c: char| c.is_whitespace() || c == '>' || c == '/')?];
+    // ignore a namespace prefix such as `atom:feed`
+    match name.rsplit(':').next()? {
+        "rss" | "RDF" => Some(FeedFormat::Rss),
+        "feed" => Some(FeedFormat::Atom),
+        _ => None,
+    }
+}
+
+/// Read the items of a feed of any supported format
+pub fn parse(content: &[u8]) -> Result<Vec<FeedItem>, ParseError> {
+    match sniff(content) {
+        Some(FeedFormat::Rss) => parse_rss(content),
+        Some(FeedFormat::Atom) => parse_atom(content),
+        Some(FeedFormat::Json) => parse_json(content),
+        None => Err(ParseError::Unknown),
+    }
+}
+
+fn parse_rss(content: &[u8]) -> Result<Vec<FeedItem>, ParseError> {
+    let channel = rss::Channel::read_from(content).map_err(ParseError::Rss)?;
+    let items = channel
+        .items
+        .into_iter()
+        .map(|it| FeedItem {
+            title: it.title.unwrap_or_default(),
+            link: it.link,
+            enclosure: it.enclosure.map(|it| Enclosure {
+                length: it.length.trim().parse().ok(),
+                url: it.url,
+                mime_type: it.mime_type,
+            }),
+            guid: it.guid.map(|it| it.value),
+            pub_date: it.pub_date,
+        })
+        .collect();
+    Ok(items)
+}
+
+fn parse_atom(content: &[u8]) -> Result<Vec<FeedItem>, ParseError> {
+    let feed = atom_syndication::Feed::read_from(content).map_err(ParseError::Atom)?;
+    let items = feed
+        .entries()
+        .iter()
+        .map(|entry| {
+            let links = entry.links();
+            let enclosures: Vec<_> = links.iter().filter(|it| it.rel() == "enclosure").collect();
+            let enclosure = enclosures
+                .iter()
+                .find(|it| it.mime_type() == Some(BITTORRENT))
+                .or(enclosures.first());
+            let link = links.iter().find(|it| it.rel() == "alternate");
+            FeedItem {
+                title: entry.title().value.clone(),
+                link: link.map(|it| it.href().to_string()),
+                enclosure: enclosure.map(|it| Enclosure {
+                    url: it.href().to_string(),
+                    mime_type: it.mime_type().unwrap_or_default().to_string(),
+                    length: it.length().and_then(|it| it.trim().parse().ok()),
+                }),
+                guid: Some(entry.id().to_string()),
+                pub_date: Some(entry.published().unwrap_or(entry.updated()).to_rfc3339()),
+            }
+        })
+        .collect();
+    Ok(items)
+}
+
+/// JSON Feed 1.0 and 1.1, only the fields in use
+#[derive(Deserialize)]
+struct JsonFeed {
+    items: Vec<JsonItem>,
+}
+
+#[derive(Deserialize)]
+struct JsonItem {
+    /// Required by the spec, still missing in some feeds
+    id: Option<serde_json::Value>,
+    url: Option<String>,
+    title: Option<String>,
+    /// Stand in for the title, which is optional
+    summary: Option<String>,
+    content_text: Option<String>,
+    date_published: Option<String>,
+    #[serde(default)]
+    attachments: Vec<JsonAttachment>,
+}
+
+#[derive(Deserialize)]
+struct JsonAttachment {
+    url: String,
+    mime_type: String,
+    size_in_bytes: Option<u64>,
+}
+
+fn parse_json(content: &[u8]) -> Result<Vec<FeedItem>, ParseError> {
+    let feed: JsonFeed = serde_json::from_slice(content).map_err(ParseError::Json)?;
+    let items = feed
+        .items
+        .into_iter()
+        .map(|it| {
+            let torrent = it
+                .attachments
+                .iter()
+                .position(|it| it.mime_type == BITTORRENT)
+                .unwrap_or(0);
+            let attachment = it.attachments.into_iter().nth(torrent);
+            FeedItem {
+                title: it
+                    .title
+                    .or(it.summary)
+                    .or(it.content_text)
+                    .unwrap_or_default(),
+                link: it.url,
+                enclosure: attachment.map(|it| Enclosure {
+                    url: it.url,
+                    mime_type: it.mime_type,
+                    length: it.size_in_bytes,
+                }),
+                // 1.0 allowed numbers
+                guid: match it.id {
+                    Some(serde_json::Value::String(val)) => Some(val),
+                    None | Some(serde_json::Value::Null) => None,
+                    Some(val) => Some(val.to_string()),
+                },
+                pub_date: it.date_published,
+            }
+        })
+        .collect();
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(
+            sniff(b"<?xml version=\"1.0\"?>\n<!-- generated -->\n<rss version=\"2.0\">"),
+            Some(FeedFormat::Rss)
+        );
+        assert_eq!(
+            sniff(b"\xef\xbb\xbf<feed xmlns=\"http://www.w3.org/2005/Atom\">"),
+            Some(FeedFormat::Atom)
+        );
+        assert_eq!(
+            sniff(b"  {\"version\": \"https://jsonfeed.org/version/1.1\"}"),
+            Some(FeedFormat::Json)
+        );
+        assert_eq!(sniff(b"<html><body/></html>"), None);
+    }
+
+    #[test]
+    fn test_parse_rss() {
+        let items = parse(
+            br#"<rss version="2.0"><channel><title>t</title><link>l</link><description/>
+            <item><title>Show - 01</title><guid>item-1</guid><link>https://example.com/1</link>
+            <enclosure url="https://example.com/1.torrent" type="application/x-bittorrent" length="42"/>
+            <pubDate>Mon, 01 Jan 2024 00:00:00 +0000</pubDate></item>
+            </channel></rss>"#,
+        )
+        .unwrap();
+        assert_eq!(
+            items,
+            vec![FeedItem {
+                title: "Show - 01".to_string(),
+                link: Some("https://example.com/1".to_string()),
+                enclosure: Some(Enclosure {
+                    url: "https://example.com/1.torrent".to_string(),
+                    mime_type: "application/x-bittorrent".to_string(),
+                    length: Some(42),
+                }),
+                guid: Some("item-1".to_string()),
+                pub_date: Some("Mon, 01 Jan 2024 00:00:00 +0000".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_atom() {
+        let items = parse(
+            br#"<?xml version="1.0" encoding="utf-8"?>
+            <feed xmlns="http://www.w3.org/2005/Atom">
+              <title>releases</title><id>urn:feed</id><updated>2024-01-01T00:00:00Z</updated>
+              <entry>
+                <title>Distro 1.0</title>
+                <id>tag:example.com,2024:1</id>
+                <updated>2024-01-02T00:00:00Z</updated>
+                <link href="https://example.com/distro-1.0"/>
+                <link rel="enclosure" type="text/plain" href="https://example.com/distro-1.0.sha256"/>
+                <link rel="enclosure" type="application/x-bittorrent" length="42"
+                      href="https://example.com/distro-1.0.torrent"/>
+              </entry>
+            </feed>"#,
+        )
+        .unwrap();
+        assert_eq!(
+            items,
+            vec![FeedItem {
+                title: "Distro 1.0".to_string(),
+                link: Some("https://example.com/distro-1.0".to_string()),
+                enclosure: Some(Enclosure {
+                    url: "https://example.com/distro-1.0.torrent".to_string(),
+                    mime_type: "application/x-bittorrent".to_string(),
+                    length: Some(42),
+                }),
+                guid: Some("tag:example.com,2024:1".to_string()),
+                pub_date: Some("2024-01-02T00:00:00+00:00".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let items = parse(
+            br#"{
+              "version": "https://jsonfeed.org/version/1.1",
+              "title": "releases",
+              "items": [{
+                "id": "1",
+                "url": "https://example.com/distro-1.0",
+                "title": "Distro 1.0",
+                "date_published": "2024-01-02T00:00:00Z",
+                "attachments": [{
+                  "url": "https://example.com/distro-1.0.iso",
+                  "mime_type": "application/octet-stream"
+                }, {
+                  "url": "magnet:?xt=urn:btih:d70db7716583224da1684de8fa324822461917aa",
+                  "mime_type": "application/x-bittorrent"
+                }]
+              }, {
+                "content_text": "Distro 1.1",
+                "url": "https://example.com/distro-1.1"
+              }]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].guid.as_deref(), Some("1"));
+        assert_eq!(
+            items[0].enclosure.as_ref().unwrap().url,
+            "magnet:?xt=urn:btih:d70db7716583224da1684de8fa324822461917aa"
+        );
+        assert_eq!(items[1].title, "Distro 1.1");
+        assert_eq!(items[1].guid, None);
+        assert_eq!(items[1].enclosure, None);
+
+        assert!(matches!(parse(b"not a feed"), Err(ParseError::Unknown)));
+    }
+}
@@ -0,0 +1,146 @@
+use crate::config::FilterRules;
+use regex::{Regex, RegexBuilder};
+
+/// Compiled [`FilterRules`] of a feed
+#[derive(Debug)]
+pub struct Filter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    regex: Vec<Regex>,
+    exclude_regex: Vec<Regex>,
+    all: Vec<Vec<String>>,
+    case_insensitive: bool,
+}
+
+impl Filter {
+    pub fn new(rules: &FilterRules) -> Result<Self, regex::Error> {
+        let case_insensitive = rules.case_insensitive;
+        let normalize = |list: &Vec<String>| -> Vec<String> {
+            list.iter()
+                .map(|it| {
+                    if case_insensitive {
+                        it.to_lowercase()
+                    } else {
+                        it.clone()
+                    }
+                })
+                .collect()
+        };
+        let compile = |list: &Vec<String>| -> Result<Vec<Regex>, regex::Error> {
+            list.iter()
+                .map(|it| {
+                    RegexBuilder::new(it)
+                        .case_insensitive(case_insensitive)
+                        .build()
+                })
+                .collect()
+        };
+
+        Ok(Filter {
+            include: normalize(&rules.include),
+            exclude: normalize(&rules.exclude),
+            regex: compile(&rules.regex)?,
+            exclude_regex: compile(&rules.exclude_regex)?,
+            all: rules.all.iter().map(normalize).collect(),
+            case_insensitive,
+        })
+    }
+
+    /// Check a title against the rules.
+    ///
+    /// A title is kept when no include rule is configured or any of them matches,
+    /// and none of the exclude rules match.
+    pub fn matches(&self, title: &str) -> bool {
+        let raw = title;
+        let title = if self.case_insensitive {
+            title.to_lowercase()
+        } else {
+            title.to_string()
+        };
+
+        if self.exclude.iter().any(|it| title.contains(it.as_str()))
+            || self.exclude_regex.iter().any(|it| it.is_match(raw))
+        {
+            return false;
+        }
+
+        if self.include.is_empty() && self.regex.is_empty() && self.all.is_empty() {
+            return true;
+        }
+        self.include.iter().any(|it| title.contains(it.as_str()))
+            || self.regex.iter().any(|it| it.is_match(raw))
+            || self
+                .all
+                .iter()
+                .any(|group| group.iter().all(|it| title.contains(it.as_str())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::RssList;
+
+    fn parse(filters: &str) -> Filter {
+        let feed = format!(
+            "title = \"test\"\nurl = \"http://localhost\"\ndownload_dir = \"/tmp\"\n{}",
+            filters
+        );
+        let feed: RssList = toml::from_str(&feed).unwrap();
+        Filter::new(&feed.filters).unwrap()
+    }
+
+    #[test]
+    fn test_legacy_list() {
+        let filter = parse(r#"filters = ["1080p", "2160p"]"#);
+        assert!(filter.matches("[Group] Show - 01 [1080p]"));
+        assert!(!filter.matches("[Group] Show - 01 [720p]"));
+        assert!(!filter.matches("[Group] Show - 01 [1080P]"));
+    }
+
+    #[test]
+    fn test_no_filters() {
+        let filter = parse("");
+        assert!(filter.matches("anything"));
+    }
+
+    #[test]
+    fn test_rules() {
+        let filter = parse(
+            r#"
+            [filters]
+            exclude = ["720p"]
+            regex = ['S\d+E\d+']
+            all = [["frieren", "1080p"]]
+            case_insensitive = true
+            "#,
+        );
+        assert!(filter.matches("Show S01E02 1080p"));
+        assert!(filter.matches("[Group] Sousou no FRIEREN - 22 [1080P]"));
+        assert!(!filter.matches("[Group] Sousou no Frieren - 22 [720p]"));
+        assert!(!filter.matches("Show S01E02 720P"));
+        assert!(!filter.matches("[Group] Sousou no Frieren - 22"));
+    }
+
+    #[test]
+    fn test_invalid_regex() {
+        let feed: RssList = toml::from_str(
+            "title = \"t\"\nurl = \"u\"\ndownload_dir = \"d\"\nfilters = { regex = [\"(\"] }",
+        )
+        .unwrap();
+        assert!(Filter::new(&feed.filters).is_err());
+    }
+
+    #[test]
+    fn test_unknown_rule() {
+        let err = toml::from_str::<RssList>(
+            "title = \"t\"\nurl = \"u\"\ndownload_dir = \"d\"\nfilters = { includes = [\"1080p\"] }",
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("unknown filter rule \"includes\""),
+            "{}",
+            err
+        );
+    }
+}
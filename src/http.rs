@@ -0,0 +1,124 @@
+//! HTTP clients of the feeds, built once for every distinct [`HttpOptions`] of a run and shared by
+//! the feed and .torrent requests, so connections and cookies of a tracker are reused.
+
+use crate::config::HttpOptions;
+use crate::error::{Error, Result};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, COOKIE};
+use reqwest::{Certificate, Client, Proxy};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Clients of a run by their options, cheap to clone and shared by the clones
+#[derive(Clone, Default)]
+pub struct Clients(Arc<Mutex<HashMap<HttpOptions, Client>>>);
+
+impl Clients {
+    /// Client applying `options`, the same one for every feed with the same options
+    pub fn get(&self, options: &HttpOptions) -> Result<Client> {
+        let mut clients = self.0.lock().unwrap();
+        if let Some(client) = clients.get(options) {
+            return Ok(client.clone());
+        }
+        let client = build(options)?;
+        clients.insert(options.clone(), client.clone());
+        Ok(client)
+    }
+}
+
+fn build(options: &HttpOptions) -> Result<Client> {
+    let invalid = |err: Box<dyn std::error::Error + Send + Sync>| Error::HttpOptions(err);
+
+    let mut headers = HeaderMap::new();
+    for (name, value) in options.headers.iter() {
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|err| invalid(err.into()))?;
+        let value = HeaderValue::from_str(value).map_err(|err| invalid(err.into()))?;
+        headers.insert(name, value);
+    }
+    if let Some(cookies) = &options.cookies {
+        let value = HeaderValue::from_str(cookies).map_err(|err| invalid(err.into()))?;
+        headers.insert(COOKIE, value);
+    }
+
+    let mut builder = Client::builder()
+        .default_headers(headers)
+        .danger_accept_invalid_certs(options.accept_invalid_certs);
+    if let Some(user_agent) = &options.user_agent {
+        builder = builder.user_agent(user_agent);
+    }
+    if let Some(proxy) = &options.proxy {
+        builder = builder.proxy(Proxy::all(proxy).map_err(|err| invalid(err.into()))?);
+    }
+    if let Some(timeout) = options.timeout {
+        builder = builder.timeout(Duration::from_secs(timeout));
+    }
+    if let Some(path) = &options.ca_file {
+        let pem = std::fs::read(path).map_err(|source| Error::Io {
+            path: path.clone(),
+            source,
+        })?;
+        let cert = Certificate::from_pem(&pem).map_err(|err| invalid(err.into()))?;
+        builder = builder.add_root_certificate(cert);
+    }
+    builder.build().map_err(|err| invalid(err.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+    use std::collections::BTreeMap;
+
+    #[tokio::test]
+    async fn test_client() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path("/rss")
+                    .header("Authorization", "passkey")
+                    .header("Cookie", "uid=1; pass=abc")
+                    .header("User-Agent", "Mozilla/5.0");
+                then.status(200);
+            })
+            .await;
+        let options = HttpOptions {
+            headers: BTreeMap::from([("Authorization".to_string(), "passkey".to_string())]),
+            cookies: Some("uid=1; pass=abc".to_string()),
+            user_agent: Some("Mozilla/5.0".to_string()),
+            timeout: Some(5),
+            ..Default::default()
+        };
+
+        let clients = Clients::default();
+
+        let res = clients
+            .get(&options)
+            .unwrap()
+            .get(server.url("/rss"))
+            .send()
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+        assert!(res.status().is_success());
+        // reused for the same options
+        clients.clone().get(&options).unwrap();
+        assert_eq!(clients.0.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let options = HttpOptions {
+            proxy: Some("not a proxy".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(build(&options), Err(Error::HttpOptions(_))));
+
+        let options = HttpOptions {
+            headers: BTreeMap::from([("bad header".to_string(), "value".to_string())]),
+            ..Default::default()
+        };
+        assert!(matches!(build(&options), Err(Error::HttpOptions(_))));
+    }
+}
@@ -1,3 +1,15 @@
+pub mod approval;
+pub mod bot;
+pub mod check;
 pub mod config;
+pub mod daemon;
+pub mod error;
+pub mod failure;
+pub mod feed;
+pub mod filter;
+pub mod http;
+pub mod limit;
+pub mod magnet;
 pub mod notification;
+pub mod retry;
 pub mod rss;
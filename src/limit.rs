@@ -0,0 +1,128 @@
+use crate::config::Concurrency;
+use reqwest::Url;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, SemaphorePermit};
+
+/// Concurrency limits shared by every feed of a run
+pub struct Limits {
+    feeds: Semaphore,
+    torrents: Semaphore,
+    per_host: usize,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+/// Held while a request is running, releases its slots on drop
+pub struct Permit<'a> {
+    _global: Option<SemaphorePermit<'a>>,
+    _host: Option<OwnedSemaphorePermit>,
+}
+
+impl Limits {
+    pub fn new(cfg: &Concurrency) -> Self {
+        Limits {
+            feeds: Semaphore::new(cfg.feeds.max(1)),
+            torrents: Semaphore::new(cfg.torrents.max(1)),
+            per_host: cfg.per_host.max(1),
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Slot for processing a whole feed, to be held until its torrents are added
+    pub async fn feed(&self) -> SemaphorePermit<'_> {
+        self.feeds
+            .acquire()
+            .await
+            .expect("semaphore is never closed")
+    }
+
+    /// Slot for fetching a feed body, only limited per host as the feed slot is already held
+    pub async fn feed_fetch(&self, url: &str) -> Permit<'_> {
+        Permit {
+            _global: None,
+            _host: self.host(url).await,
+        }
+    }
+
+    /// Slot for fetching a .torrent file
+    pub async fn torrent_fetch(&self, url: &str) -> Permit<'_> {
+        // host first, so a busy host doesn't hold global slots other hosts could use
+        let host = self.host(url).await;
+        Permit {
+            _global: Some(
+                self.torrents
+                    .acquire()
+                    .await
+                    .expect("semaphore is never closed"),
+            ),
+            _host: host,
+        }
+    }
+
+    async fn host(&self, url: &str) -> Option<OwnedSemaphorePermit> {
+        let host = Url::parse(url).ok()?.host_str()?.to_string();
+        let semaphore = self
+            .hosts
+            .lock()
+            .unwrap()
+            .entry(host)
+            .or_insert_with(|| Arc::new(Semaphore::new(self.per_host)))
+            .clone();
+        semaphore.acquire_owned().await.ok()
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits::new(&Concurrency::default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_per_host() {
+        let limits = Limits::new(&Concurrency {
+            feeds: 1,
+            torrents: 4,
+            per_host: 1,
+        });
+
+        let _permit = limits.torrent_fetch("https://a.example/1.torrent").await;
+        // another host is still free
+        let other = limits.torrent_fetch("https://b.example/1.torrent");
+        assert!(tokio::time::timeout(Duration::from_millis(50), other)
+            .await
+            .is_ok());
+        // the same host has to wait
+        let same = limits.torrent_fetch("https://a.example/2.torrent");
+        assert!(tokio::time::timeout(Duration::from_millis(50), same)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_global() {
+        let limits = Limits::new(&Concurrency {
+            feeds: 1,
+            torrents: 1,
+            per_host: 4,
+        });
+
+        let _permit = limits.torrent_fetch("https://a.example/1.torrent").await;
+        let other = limits.torrent_fetch("https://b.example/1.torrent");
+        assert!(tokio::time::timeout(Duration::from_millis(50), other)
+            .await
+            .is_err());
+
+        let _feed = limits.feed().await;
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), limits.feed())
+                .await
+                .is_err()
+        );
+    }
+}
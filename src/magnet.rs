@@ -0,0 +1,87 @@
+use reqwest::Url;
+
+const BTIH_PREFIX: &str = "urn:btih:";
+
+pub fn is_magnet(link: &str) -> bool {
+    link.get(..7)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("magnet:"))
+}
+
+/// Extract the info-hash of a `magnet:?xt=urn:btih:...` link as lowercase hex,
+/// the same form as `Torrent::info_hash` and transmission's `hashString`
+pub fn info_hash(link: &str) -> Option<String> {
+    if !is_magnet(link) {
+        return None;
+    }
+    let url = Url::parse(link).ok()?;
+    let hash = url.query_pairs().find_map(|(key, value)| {
+        if key != "xt" {
+            return None;
+        }
+        // the value is decoded from the feed, the prefix length may not be a char boundary
+        let prefix = value.get(..BTIH_PREFIX.len())?;
+        if prefix.eq_ignore_ascii_case(BTIH_PREFIX) {
+            Some(value[BTIH_PREFIX.len()..].to_string())
+        } else {
+            None
+        }
+    })?;
+
+    match hash.len() {
+        40 if hash.chars().all(|c| c.is_ascii_hexdigit()) => Some(hash.to_lowercase()),
+        32 => base32_to_hex(&hash),
+        _ => None,
+    }
+}
+
+/// Decode the RFC 4648 base32 form of a 20 bytes info-hash into hex
+fn base32_to_hex(hash: &str) -> Option<String> {
+    let mut bits: u64 = 0;
+    let mut len = 0;
+    let mut hex = String::with_capacity(40);
+    for c in hash.chars() {
+        let value = match c.to_ascii_uppercase() {
+            c @ 'A'..='Z' => c as u64 - 'A' as u64,
+            c @ '2'..='7' => c as u64 - '2' as u64 + 26,
+            _ => return None,
+        };
+        bits = (bits << 5) | value;
+        len += 5;
+        if len >= 8 {
+            len -= 8;
+            hex.push_str(&format!("{:02x}", (bits >> len) & 0xff));
+        }
+    }
+    Some(hex)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_info_hash() {
+        let hex = "d70db7716583224da1684de8fa324822461917aa";
+        let link = format!(
+            "magnet:?xt=urn:btih:{}&dn=test&tr=http%3A%2F%2Ftracker",
+            hex.to_uppercase()
+        );
+        assert_eq!(info_hash(&link).as_deref(), Some(hex));
+        assert_eq!(
+            info_hash("magnet:?dn=test&xt=urn:btih:24G3O4LFQMRE3ILIJXUPUMSIEJDBSF5K").as_deref(),
+            Some(hex)
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(info_hash("https://example.com/1.torrent").is_none());
+        assert!(info_hash("magnet:?dn=test").is_none());
+        assert!(info_hash("magnet:?xt=urn:btih:1234").is_none());
+        assert!(info_hash("magnet:?xt=urn:bti%E2%82%AC24G3O4LFQMRE3ILIJXUPUMSIEJDBSF5K").is_none());
+        assert!(info_hash("magnet:?xt=urn:bti€h").is_none());
+        assert!(
+            info_hash("magnet:?xt=urn:sha1:d70db7716583224da1684de8fa324822461917aa").is_none()
+        );
+    }
+}
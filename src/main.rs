@@ -1,10 +1,17 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use futures::FutureExt;
 use sled::Db;
-use std::error::Error;
-use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use transmission_rss::config::Config;
-use transmission_rss::rss::{get_client, process_feed};
+use transmission_rss::check::check_files;
+use transmission_rss::config::{Config, Sources};
+use transmission_rss::daemon;
+use transmission_rss::error::{Error, Result};
+use transmission_rss::failure::{self, Failure};
+use transmission_rss::http::Clients;
+use transmission_rss::limit::Limits;
+use transmission_rss::notification::{Event, FeedSummary, Registry};
+use transmission_rss::rss::{dry_run_feed, get_client, process_feed, rpc_call};
 
 /// Parse args
 #[derive(Parser, Debug)]
@@ -17,27 +24,63 @@ struct Args {
     // Path to rss rules
     #[clap(long)]
     rules: Option<String>,
+
+    /// Keep running and poll every feed on its own interval
+    #[clap(long)]
+    daemon: bool,
+
+    /// Print the items that would be added without adding them, writing the db or notifying
+    #[clap(long, conflicts_with = "daemon")]
+    dry_run: bool,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Validate the config and rules files without contacting any server
+    CheckConfig,
+    /// List or clear the feed items quarantined after failing too often
+    Quarantine {
+        #[clap(subcommand)]
+        action: QuarantineAction,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum QuarantineAction {
+    /// Print the quarantined items with their last error
+    List,
+    /// Try the item again on the next run, every quarantined item without a key
+    Clear { key: Option<String> },
 }
 
-pub async fn init_db(cfg: &Config) -> Result<Arc<Db>, Box<dyn Error + Send + Sync>> {
+pub async fn init_db(cfg: &Config) -> Result<Arc<Db>> {
     let db = sled::open(&cfg.persistence.path)?;
     if db.was_recovered() {
         log::info!("Database recovered");
     }
 
     // fetch updated torrents
-    let mut client = get_client(cfg);
-    let res = client.torrent_get(None, None).await;
-    if res.is_err() {
-        return Err(format!(
-            "Failed to fetch torrents: {:?}, please check transmission server.",
-            res
-        )
-        .into());
-    }
-    let res = res.unwrap();
+    let mut client = get_client(cfg)?;
+    let res = rpc_call(
+        cfg,
+        &cfg.retry,
+        &mut client,
+        |client| client.torrent_get(None, None).boxed(),
+        |result| Error::Transmission(result.into()),
+    )
+    .await;
+    let torrents = match res {
+        Ok(val) => val.torrents,
+        Err(err) => {
+            log::error!("Failed to fetch torrents, please check transmission server.");
+            return Err(err);
+        }
+    };
     let mut update_count = 0;
-    for torrent in res.arguments.torrents {
+    for torrent in torrents {
         let torrent_hash = torrent.hash_string.unwrap();
         if let Ok(rt) = db.get(&torrent_hash) {
             if rt.is_some() {
@@ -45,14 +88,14 @@ pub async fn init_db(cfg: &Config) -> Result<Arc<Db>, Box<dyn Error + Send + Syn
             }
         }
         update_count += 1;
-        db.insert(&torrent_hash, b"").unwrap();
+        db.insert(&torrent_hash, b"")?;
     }
     log::info!("update db with {:?} items", update_count);
     Ok(Arc::new(db))
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() {
     let _ = env_logger::builder()
         .filter_level(log::LevelFilter::Info)
         .is_test(true)
@@ -61,46 +104,204 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Read env
     let args = Args::parse();
 
-    // Read initial config file
-    let mut file = match fs::read_to_string(&args.config) {
+    if let Err(err) = run(args).await {
+        log::error!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+async fn run(args: Args) -> Result<()> {
+    if let Some(Command::CheckConfig) = args.command {
+        let problems = check_files(&args.config, args.rules.as_deref());
+        for problem in problems.iter() {
+            eprintln!("{}", problem);
+        }
+        if !problems.is_empty() {
+            eprintln!("{} problems found", problems.len());
+            std::process::exit(1);
+        }
+        println!("Config OK");
+        return Ok(());
+    }
+
+    // Read initial config file and append rules to it
+    let sources = Sources::read(&args.config, args.rules.as_deref())?;
+    let cfg: Config = toml::from_str(sources.merged())?;
+    if let Some(Command::Quarantine { action }) = args.command {
+        // only the db is needed, not transmission
+        let db = sled::open(&cfg.persistence.path)?;
+        quarantine(&db, action)?;
+        return Ok(());
+    }
+    let notifiers = Registry::from_config(&cfg.notification)?.with_retry(cfg.retry.clone());
+
+    if args.dry_run {
+        // skip init_db, it would sync the db with transmission
+        let db = Arc::new(dry_run_db(&cfg.persistence.path)?);
+        dry_run(db, &cfg).await;
+        return Ok(());
+    }
+
+    let db: Arc<Db> = match init_db(&cfg).await {
         Ok(val) => val,
-        Err(err) => panic!("Failed to find file {:?}: {}", args.config, err),
+        Err(err) => {
+            if err.is_transmission() {
+                let event = Event::TransmissionUnreachable {
+                    error: err.to_string(),
+                };
+                notifiers.notify_all(&event).await;
+            }
+            return Err(err);
+        }
     };
-
-    // append rules to config file
-    if let Some(rules) = args.rules {
-        let rules = match fs::read_to_string(&rules) {
-            Ok(val) => val,
-            Err(err) => panic!("Failed to find file {:?}: {}", rules, err),
-        };
-        file.push('\n');
-        file.push_str(&rules);
+    let notifiers = Arc::new(notifiers.with_outbox(&db));
+    if let Err(err) = notifiers.retry_outbox().await {
+        log::warn!("Failed to retry the notification outbox: {}", err);
     }
 
-    let cfg: Config = toml::from_str(&file).unwrap();
-    let db: Arc<Db> = init_db(&cfg).await.unwrap();
+    if args.daemon {
+        daemon::run(db, cfg, notifiers).await?;
+        return Ok(());
+    }
 
+    let limits = Arc::new(Limits::new(&cfg.concurrency));
+    let clients = Clients::default();
+    // every feed fails the same way when transmission is down, it's reported once
+    let unreachable = AtomicBool::new(false);
     let items: Vec<_> = cfg
         .clone()
         .rss_list
         .into_iter()
         .map(|it| async {
             let title = it.title.clone();
-            let rt = process_feed(db.clone(), it, cfg.clone()).await;
-            if let Err(err) = rt {
+            let rt = process_feed(
+                db.clone(),
+                it,
+                cfg.clone(),
+                &clients,
+                limits.clone(),
+                notifiers.clone(),
+            )
+            .await;
+            if let Err(err) = &rt {
                 let msg = format!("Failed to process {} feed: {}", title, err);
                 log::error!("{}", msg);
-                // notify_all(cfg.clone(), msg).await;
+                let event = Event::failure(&title, err);
+                let repeated = matches!(event, Event::TransmissionUnreachable { .. })
+                    && unreachable.swap(true, Ordering::Relaxed);
+                if !repeated {
+                    notifiers.notify_all(&event).await;
+                }
+            }
+            FeedSummary {
+                feed: title,
+                result: rt.map_err(|err| err.to_string()),
             }
         })
         .collect();
-    // _ = futures::future::join_all(items).await;
-    for item in items {
-        item.await;
+    let feeds = futures::future::join_all(items).await;
+    notifiers.notify_all(&Event::Summary { feeds }).await;
+    notifiers.flush().await;
+    Ok(())
+}
+
+/// The db at `path` without creating it, or an empty temporary one when there's none yet.
+///
+/// sled has no read only mode, a db locked by a running daemon is an error rather than listing
+/// the items it already has.
+fn dry_run_db(path: &str) -> Result<Db> {
+    if !std::path::Path::new(path).exists() {
+        log::warn!("No db at {:?}, every matching item is listed", path);
+        return Ok(sled::Config::new().temporary(true).open()?);
+    }
+    sled::open(path).map_err(|source| Error::DbLocked {
+        path: path.to_string(),
+        source,
+    })
+}
+
+async fn dry_run(db: Arc<Db>, cfg: &Config) {
+    let limits = Arc::new(Limits::new(&cfg.concurrency));
+    let clients = Clients::default();
+    let results = futures::future::join_all(cfg.rss_list.iter().map(|feed| {
+        dry_run_feed(
+            db.clone(),
+            feed.clone(),
+            cfg.clone(),
+            &clients,
+            limits.clone(),
+        )
+    }))
+    .await;
+
+    let mut rows = Vec::new();
+    for (feed, result) in cfg.rss_list.iter().zip(results) {
+        match result {
+            Ok(titles) => rows.extend(
+                titles
+                    .into_iter()
+                    .map(|title| [feed.title.clone(), title, feed.download_dir.clone()]),
+            ),
+            Err(err) => log::error!("Failed to process {} feed: {}", feed.title, err),
+        }
+    }
+
+    if rows.is_empty() {
+        println!("Nothing would be added");
+        return;
+    }
+    print!("{}", format_table(["FEED", "TITLE", "DOWNLOAD DIR"], &rows));
+}
+
+fn quarantine(db: &Db, action: QuarantineAction) -> Result<()> {
+    match action {
+        QuarantineAction::List => {
+            let rows: Vec<_> = Failure::list(db)?
+                .into_iter()
+                .filter(|it| it.quarantined)
+                .map(|it| [it.feed, it.title, it.key, it.last_error])
+                .collect();
+            if rows.is_empty() {
+                println!("Nothing is quarantined");
+                return Ok(());
+            }
+            print!(
+                "{}",
+                format_table(["FEED", "TITLE", "KEY", "LAST ERROR"], &rows)
+            );
+        }
+        QuarantineAction::Clear { key } => {
+            let count = failure::clear(db, key.as_deref())?;
+            println!("{} items released from quarantine", count);
+        }
     }
     Ok(())
 }
 
+fn format_table<const N: usize>(header: [&str; N], rows: &[[String; N]]) -> String {
+    let mut widths = header.map(|it| it.chars().count());
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_row = |row: [&str; N]| {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{}{}", cell, " ".repeat(width - cell.chars().count())))
+            .collect::<Vec<_>>()
+            .join("  ");
+        format!("{}\n", line.trim_end())
+    };
+    let mut table = format_row(header);
+    for row in rows {
+        table.push_str(&format_row(row.each_ref().map(String::as_str)));
+    }
+    table
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -127,4 +328,35 @@ mod test {
             .try_init();
         init_db(&cfg).await.unwrap();
     }
+
+    #[test]
+    fn test_dry_run_db() {
+        let path = std::env::temp_dir().join(format!("dry-run-db-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        // not created when missing
+        dry_run_db(path).unwrap();
+        assert!(!std::path::Path::new(path).exists());
+
+        // locked by a running daemon
+        let db = sled::open(path).unwrap();
+        assert!(matches!(dry_run_db(path), Err(Error::DbLocked { .. })));
+        drop(db);
+        dry_run_db(path).unwrap();
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_format_table() {
+        let rows = [[
+            "feed".to_string(),
+            "Show - 01".to_string(),
+            "/downloads".to_string(),
+        ]];
+        let table = format_table(["FEED", "TITLE", "DOWNLOAD DIR"], &rows);
+        assert_eq!(
+            table,
+            "FEED  TITLE      DOWNLOAD DIR\nfeed  Show - 01  /downloads\n"
+        );
+    }
 }
@@ -0,0 +1,141 @@
+use crate::config::{AppriseNotification, Priority};
+use crate::error::{Error, Result};
+use crate::notification::delivery::check_status;
+use crate::notification::{Event, Notifier};
+use async_trait::async_trait;
+use serde::Serialize;
+
+const BACKEND: &str = "apprise";
+
+#[derive(Serialize)]
+struct Message {
+    title: String,
+    body: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+pub struct Apprise {
+    cfg: AppriseNotification,
+}
+
+impl Apprise {
+    pub fn new(cfg: AppriseNotification) -> Self {
+        Self { cfg }
+    }
+
+    pub async fn send(&self, event: &Event) -> Result<()> {
+        let priority = self
+            .cfg
+            .priority
+            .get(&event.kind())
+            .copied()
+            .unwrap_or_default();
+        let message = Message {
+            title: event.title().to_string(),
+            body: event.message(),
+            kind: apprise_type(priority),
+        };
+
+        let client = reqwest::Client::new();
+        let res = client
+            .post(format!(
+                "{}/notify/{}",
+                self.cfg.server.trim_end_matches('/'),
+                self.cfg.key
+            ))
+            .json(&message)
+            .send()
+            .await
+            .map_err(|err| Error::Notification {
+                backend: BACKEND.to_string(),
+                source: err.into(),
+            })?;
+
+        check_status(BACKEND, res).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Notifier for Apprise {
+    fn name(&self) -> &str {
+        BACKEND
+    }
+
+    async fn notify(&self, event: &Event) -> Result<()> {
+        self.send(event).await
+    }
+}
+
+// apprise has no priority, only a notification type that services render differently
+fn apprise_type(priority: Priority) -> &'static str {
+    match priority {
+        Priority::Min | Priority::Low | Priority::Default => "info",
+        Priority::High => "warning",
+        Priority::Max => "failure",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notification::AddedTorrent;
+    use httpmock::prelude::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_send() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/notify/torrents").json_body(json!({
+                "title": "Transmission unreachable",
+                "body": "Transmission unreachable: timeout",
+                "type": "warning",
+            }));
+            then.status(200);
+        });
+        let cfg: AppriseNotification = toml::from_str(&format!(
+            "server = \"{}\"\nkey = \"torrents\"",
+            server.base_url()
+        ))
+        .unwrap();
+        let notifier = Apprise::new(cfg);
+
+        let event = Event::TransmissionUnreachable {
+            error: "timeout".to_string(),
+        };
+        let result = tokio_test::block_on(notifier.send(&event));
+
+        mock.assert();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_send_rejected() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/notify/torrents");
+            then.status(424)
+                .body("One or more notification could not be sent.");
+        });
+        let cfg: AppriseNotification = toml::from_str(&format!(
+            "server = \"{}\"\nkey = \"torrents\"",
+            server.base_url()
+        ))
+        .unwrap();
+        let notifier = Apprise::new(cfg);
+
+        let event = Event::Added {
+            feed: "anime".to_string(),
+            torrent: AddedTorrent {
+                title: "episode 1".to_string(),
+                ..Default::default()
+            },
+        };
+        let result = tokio_test::block_on(notifier.send(&event));
+
+        assert!(matches!(result, Err(Error::NotificationRejected { .. })));
+    }
+}
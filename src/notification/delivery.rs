@@ -0,0 +1,119 @@
+//! Shared delivery of the notifiers: retries with exponential backoff honouring the `retry_after`
+//! of rate limited requests, a minimum interval between the messages of a notifier, and an outbox
+//! in the db for the notifications that still couldn't be delivered.
+
+use crate::error::{Error, Result};
+use crate::notification::Event;
+use crate::retry::{retry_after, RetryPolicy};
+use reqwest::{Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// sled tree of the notifications to retry on the next run
+pub const OUTBOX_TREE: &str = "outbox";
+
+/// Whether a notification that failed with `err` is worth another attempt
+pub fn is_retryable(err: &Error) -> bool {
+    matches!(
+        err,
+        Error::Notification { .. } | Error::NotificationRateLimited { .. }
+    )
+}
+
+/// Delay before retrying after `attempt` failed with `err`, `None` when retrying won't help or
+/// `retry` gave up
+pub fn delay(retry: &RetryPolicy, err: &Error, attempt: u32) -> Option<Duration> {
+    match err {
+        Error::NotificationRateLimited { retry_after, .. } => retry.delay(attempt, *retry_after),
+        Error::Notification { .. } => retry.delay(attempt, None),
+        _ => None,
+    }
+}
+
+/// A notification waiting in the outbox
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct OutboxEntry {
+    /// Position of the notifier in the registry
+    pub index: usize,
+    /// Name of the notifier, entries are dropped when the config changed
+    pub notifier: String,
+    pub event: Event,
+}
+
+/// Turn an unsuccessful response into an error, telling the retryable ones apart
+pub(crate) async fn check_status(backend: &str, res: Response) -> Result<Response> {
+    let status = res.status();
+    if status.is_success() {
+        return Ok(res);
+    }
+    let header = retry_after(res.headers());
+    let body = res.text().await.unwrap_or_default();
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = header.or_else(|| body_retry_after(&body).map(Duration::from_secs_f64));
+        return Err(Error::NotificationRateLimited {
+            backend: backend.to_string(),
+            retry_after,
+        });
+    }
+    if status.is_server_error() {
+        return Err(Error::Notification {
+            backend: backend.to_string(),
+            source: format!("{}: {}", status, body).into(),
+        });
+    }
+    Err(Error::NotificationRejected {
+        backend: backend.to_string(),
+        message: body,
+    })
+}
+
+/// Seconds to wait from a JSON body, telegram's `parameters.retry_after` or discord's `retry_after`
+fn body_retry_after(body: &str) -> Option<f64> {
+    let value: serde_json::Value = serde_json::from_str(body).ok()?;
+    value
+        .pointer("/parameters/retry_after")
+        .or_else(|| value.get("retry_after"))
+        .and_then(|it| it.as_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay() {
+        let retry = RetryPolicy {
+            jitter: 0.0,
+            ..Default::default()
+        };
+        let failed = Error::Notification {
+            backend: "test".to_string(),
+            source: "timeout".into(),
+        };
+        assert_eq!(delay(&retry, &failed, 1), Some(Duration::from_secs(1)));
+        assert_eq!(delay(&retry, &failed, 3), Some(Duration::from_secs(4)));
+        assert_eq!(delay(&retry, &failed, 4), None);
+        assert!(is_retryable(&failed));
+
+        let limited = Error::NotificationRateLimited {
+            backend: "test".to_string(),
+            retry_after: Some(Duration::from_secs(7)),
+        };
+        assert_eq!(delay(&retry, &limited, 1), Some(Duration::from_secs(7)));
+
+        let rejected = Error::NotificationRejected {
+            backend: "test".to_string(),
+            message: "chat not found".to_string(),
+        };
+        assert_eq!(delay(&retry, &rejected, 1), None);
+        assert!(!is_retryable(&rejected));
+    }
+
+    #[test]
+    fn test_body_retry_after() {
+        let telegram = r#"{"ok":false,"error_code":429,"parameters":{"retry_after":5}}"#;
+        assert_eq!(body_retry_after(telegram), Some(5.0));
+        assert_eq!(body_retry_after(r#"{"retry_after":0.5}"#), Some(0.5));
+        assert_eq!(body_retry_after("Too Many Requests"), None);
+    }
+}
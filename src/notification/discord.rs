@@ -0,0 +1,103 @@
+use crate::error::{Error, Result};
+use crate::notification::delivery::check_status;
+use crate::notification::{Event, Notifier};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::time::Duration;
+
+const BACKEND: &str = "discord";
+/// Longest `content` discord accepts
+const MAX_CONTENT: usize = 2000;
+
+#[derive(Serialize)]
+struct Message {
+    content: String,
+}
+
+pub struct Discord {
+    webhook: String,
+}
+
+impl Discord {
+    pub fn new(webhook: String) -> Self {
+        Self { webhook }
+    }
+
+    pub async fn send(&self, message: String) -> Result<()> {
+        let content = match message.char_indices().nth(MAX_CONTENT) {
+            Some((end, _)) => message[..end].to_string(),
+            None => message,
+        };
+
+        let client = reqwest::Client::new();
+        let res = client
+            .post(&self.webhook)
+            .json(&Message { content })
+            .send()
+            .await
+            .map_err(|err| Error::Notification {
+                backend: BACKEND.to_string(),
+                source: err.into(),
+            })?;
+
+        // 204 unless the webhook url has `?wait=true`
+        check_status(BACKEND, res).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Notifier for Discord {
+    fn name(&self) -> &str {
+        BACKEND
+    }
+
+    /// Webhooks allow 5 requests every 2 seconds
+    fn rate_limit(&self) -> Duration {
+        Duration::from_millis(500)
+    }
+
+    async fn notify(&self, event: &Event) -> Result<()> {
+        self.send(event.message()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_send() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/api/webhooks/1/token")
+                .json_body(json!({ "content": "test message" }));
+            then.status(204);
+        });
+        let notifier = Discord::new(server.url("/api/webhooks/1/token"));
+
+        let result = tokio_test::block_on(notifier.send("test message".into()));
+
+        mock.assert();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_send_rejected() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/api/webhooks/1/token");
+            then.status(404)
+                .body(r#"{"message": "Unknown Webhook", "code": 10015}"#);
+        });
+        let notifier = Discord::new(server.url("/api/webhooks/1/token"));
+
+        let result = tokio_test::block_on(notifier.send("test message".into()));
+
+        assert!(matches!(result, Err(Error::NotificationRejected { .. })));
+    }
+}
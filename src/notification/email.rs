@@ -0,0 +1,207 @@
+use crate::config::{EmailNotification, SmtpTls};
+use crate::error::{Error, Result};
+use crate::notification::{Event, Notifier};
+use async_trait::async_trait;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::sync::Mutex;
+
+const BACKEND: &str = "email";
+
+pub struct Email {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    digest: bool,
+    /// `added` events waiting for the digest
+    pending: Mutex<Vec<Event>>,
+}
+
+impl Email {
+    /// Fails with a config error when an address can't be parsed
+    pub fn new(cfg: &EmailNotification) -> Result<Self> {
+        let from = mailbox(&cfg.from)?;
+        let to = cfg.to.iter().map(|it| mailbox(it)).collect::<Result<_>>()?;
+        let builder = match cfg.tls {
+            SmtpTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&cfg.host),
+            SmtpTls::Implicit => AsyncSmtpTransport::<Tokio1Executor>::relay(&cfg.host),
+            SmtpTls::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                &cfg.host,
+            )),
+        };
+        let mut builder = builder.map_err(notification_error)?.port(cfg.port);
+        if let Some(username) = &cfg.username {
+            let password = cfg.password.clone().unwrap_or_default();
+            builder = builder.credentials(Credentials::new(username.clone(), password));
+        }
+        Ok(Self {
+            transport: builder.build(),
+            from,
+            to,
+            digest: cfg.digest,
+            pending: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub async fn send(&self, subject: &str, body: String) -> Result<()> {
+        let mut builder = Message::builder()
+            .from(self.from.clone())
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN);
+        for to in self.to.iter() {
+            builder = builder.to(to.clone());
+        }
+        let message = builder.body(body).map_err(notification_error)?;
+
+        let res = self.transport.send(message).await;
+        match res {
+            Ok(_) => Ok(()),
+            Err(err) if err.is_permanent() => Err(Error::NotificationRejected {
+                backend: BACKEND.to_string(),
+                message: err.to_string(),
+            }),
+            Err(err) => Err(notification_error(err)),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for Email {
+    fn name(&self) -> &str {
+        BACKEND
+    }
+
+    async fn notify(&self, event: &Event) -> Result<()> {
+        if self.digest && matches!(event, Event::Added { .. }) {
+            self.pending.lock().unwrap().push(event.clone());
+            return Ok(());
+        }
+        let message = event.message();
+        let subject = message.lines().next().unwrap_or_default().to_string();
+        self.send(&subject, message).await
+    }
+
+    fn take_batch(&self) -> Vec<Event> {
+        std::mem::take(&mut *self.pending.lock().unwrap())
+    }
+
+    async fn send_batch(&self, events: &[Event]) -> Result<()> {
+        let subject = format!("{} torrents added", events.len());
+        let body: Vec<_> = events.iter().map(|it| it.message()).collect();
+        self.send(&subject, body.join("\n")).await
+    }
+}
+
+fn mailbox(address: &str) -> Result<Mailbox> {
+    address.parse().map_err(|err| {
+        Error::Config(serde::de::Error::custom(format!(
+            "invalid email address {:?}: {}",
+            address, err
+        )))
+    })
+}
+
+fn notification_error<E: std::error::Error + Send + Sync + 'static>(err: E) -> Error {
+    Error::Notification {
+        backend: BACKEND.to_string(),
+        source: err.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notification::AddedTorrent;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// Accept one SMTP session and return the DATA of every mail
+    async fn mock_smtp(listener: TcpListener) -> Vec<String> {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        let mut mails = Vec::new();
+        write.write_all(b"220 mock ESMTP\r\n").await.unwrap();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            let reply: &[u8] = match line.to_uppercase().split(' ').next().unwrap() {
+                "EHLO" => b"250-mock\r\n250 8BITMIME\r\n",
+                "DATA" => {
+                    write.write_all(b"354 go ahead\r\n").await.unwrap();
+                    let mut data = String::new();
+                    while let Some(line) = lines.next_line().await.unwrap() {
+                        if line == "." {
+                            break;
+                        }
+                        data.push_str(&line);
+                        data.push('\n');
+                    }
+                    mails.push(data);
+                    b"250 queued\r\n"
+                }
+                "QUIT" => {
+                    write.write_all(b"221 bye\r\n").await.unwrap();
+                    break;
+                }
+                _ => b"250 ok\r\n",
+            };
+            write.write_all(reply).await.unwrap();
+        }
+        mails
+    }
+
+    #[tokio::test]
+    async fn test_digest() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(mock_smtp(listener));
+
+        let cfg: EmailNotification = toml::from_str(&format!(
+            r#"
+host = "127.0.0.1"
+port = {}
+tls = "none"
+from = "rss@example.com"
+to = ["me@example.com"]
+digest = true
+"#,
+            port
+        ))
+        .unwrap();
+        let email = Email::new(&cfg).unwrap();
+        for title in ["episode 1", "episode 2"] {
+            let event = Event::Added {
+                feed: "anime".to_string(),
+                torrent: AddedTorrent {
+                    title: title.to_string(),
+                    ..Default::default()
+                },
+            };
+            email.notify(&event).await.unwrap();
+        }
+        let batch = email.take_batch();
+        email.send_batch(&batch).await.unwrap();
+        // nothing left to send
+        assert!(email.take_batch().is_empty());
+        drop(email);
+
+        let mails = server.await.unwrap();
+        assert_eq!(mails.len(), 1);
+        assert!(mails[0].contains("Subject: 2 torrents added"));
+        assert!(mails[0].contains("Downloading: episode 1\nDownloading: episode 2"));
+    }
+
+    #[test]
+    fn test_invalid_address() {
+        let cfg: EmailNotification = toml::from_str(
+            r#"
+host = "127.0.0.1"
+from = "rss@example.com"
+to = ["not an address"]
+"#,
+        )
+        .unwrap();
+        assert!(matches!(Email::new(&cfg), Err(Error::Config(_))));
+    }
+}
@@ -1,12 +1,38 @@
-use serde::Serialize;
-use std::error::Error as StdError;
-use super::Error;
-use reqwest::StatusCode;
+use crate::approval::{callback_link, Action};
+use crate::error::{Error, Result};
+use crate::notification::delivery::check_status;
+use crate::notification::template::Format;
+use crate::notification::{Event, Notifier};
+use async_trait::async_trait;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const BACKEND: &str = "feishu";
 
 pub struct FeiShu {
     webhook: String,
+    format: Format,
+    /// Send interactive cards instead of text messages
+    card: bool,
+    /// Public url of the approval callback server
+    callback_url: Option<String>,
+    /// Signing secret of bots with signature verification enabled
+    secret: Option<String>,
+}
+
+/// Answer of the webhook, failures come back with HTTP 200 and a non zero `code`
+#[derive(Deserialize)]
+struct FeiShuResponse {
+    code: Option<i64>,
+    msg: Option<String>,
 }
 
+/// `code` of requests over the rate limit of the bot
+const RATE_LIMITED: i64 = 9499;
+
 #[derive(Serialize)]
 pub struct FeiShuMessage {
     msg_type: String,
@@ -19,34 +45,227 @@ enum FeiShuContent {
     Text { text: String },
 }
 
+/// Interactive message, the card isn't wrapped in `content` like the other types
+#[derive(Serialize)]
+struct FeiShuCardMessage {
+    msg_type: String,
+    card: Card,
+}
+
+#[derive(Serialize)]
+struct Card {
+    header: CardHeader,
+    elements: Vec<CardElement>,
+}
+
+#[derive(Serialize)]
+struct CardHeader {
+    title: CardText,
+    /// Color of the header
+    template: String,
+}
+
+#[derive(Serialize)]
+struct CardText {
+    tag: String,
+    content: String,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "tag", rename_all = "snake_case")]
+enum CardElement {
+    Div { text: CardText },
+    Action { actions: Vec<CardButton> },
+}
+
+/// Button opening a link of the approval callback server
+#[derive(Serialize)]
+struct CardButton {
+    tag: String,
+    text: CardText,
+    url: String,
+    /// Style of the button
+    #[serde(rename = "type")]
+    kind: String,
+}
+
 impl FeiShu {
     pub fn new(webhook: String) -> Self {
-        Self { webhook }
+        Self {
+            webhook,
+            format: Format::default(),
+            card: false,
+            callback_url: None,
+            secret: None,
+        }
     }
 
-    pub async fn send(&self, message: String) -> Result<(), Box<dyn StdError>> {
+    /// Sign every request with the secret of the bot
+    pub fn with_secret(mut self, secret: Option<String>) -> Self {
+        self.secret = secret;
+        self
+    }
+
+    /// Render events with templates, as interactive cards with `card`
+    pub fn with_format(mut self, format: Format, card: bool) -> Self {
+        self.format = format;
+        self.card = card;
+        self
+    }
+
+    /// Add approval buttons linking to the callback server at `callback_url`, always sent as cards
+    pub fn with_callback_url(mut self, callback_url: Option<String>) -> Self {
+        self.callback_url = callback_url;
+        self
+    }
+
+    pub async fn send(&self, message: String) -> Result<()> {
+        self.post(&FeiShuMessage {
+            msg_type: "text".to_string(),
+            content: FeiShuContent::Text {
+                text: message.to_owned(),
+            },
+        })
+        .await
+    }
+
+    /// Send `content` as lark_md in a card titled after the event
+    pub async fn send_card(&self, event: &Event, content: String) -> Result<()> {
+        let color = match event {
+            Event::Added { .. } => "green",
+            Event::ApprovalRequested { .. } => "orange",
+            Event::FeedFailed { .. } | Event::TransmissionUnreachable { .. } => "red",
+            Event::Summary { .. } => "blue",
+        };
+        self.post(&FeiShuCardMessage {
+            msg_type: "interactive".to_string(),
+            card: Card {
+                header: CardHeader {
+                    title: CardText {
+                        tag: "plain_text".to_string(),
+                        content: event.title().to_string(),
+                    },
+                    template: color.to_string(),
+                },
+                elements: std::iter::once(CardElement::Div {
+                    text: CardText {
+                        tag: "lark_md".to_string(),
+                        content,
+                    },
+                })
+                .chain(self.buttons(event))
+                .collect(),
+            },
+        })
+        .await
+    }
+
+    /// Approval buttons of an event, if any
+    fn buttons(&self, event: &Event) -> Option<CardElement> {
+        let base_url = self.callback_url.as_deref()?;
+        let (torrent, actions) = match event {
+            Event::ApprovalRequested { torrent, .. } => (
+                torrent,
+                vec![
+                    ("Approve", Action::Approve, "primary"),
+                    ("Reject", Action::Reject, "default"),
+                ],
+            ),
+            Event::Added { torrent, .. } => (torrent, vec![("Remove", Action::Remove, "danger")]),
+            _ => return None,
+        };
+        let token = torrent.approval.as_deref()?;
+        let actions = actions
+            .into_iter()
+            .map(|(text, action, kind)| CardButton {
+                tag: "button".to_string(),
+                text: CardText {
+                    tag: "plain_text".to_string(),
+                    content: text.to_string(),
+                },
+                url: callback_link(base_url, action, &torrent.info_hash, token),
+                kind: kind.to_string(),
+            })
+            .collect();
+        Some(CardElement::Action { actions })
+    }
+
+    async fn post<T: Serialize>(&self, message: &T) -> Result<()> {
+        let mut body = serde_json::to_value(message).expect("messages always serialize");
+        if let Some(secret) = &self.secret {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            body["timestamp"] = timestamp.to_string().into();
+            body["sign"] = sign(secret, timestamp)?.into();
+        }
+
         let client = reqwest::Client::new();
         let res = client
             .post(&self.webhook)
             .header("Content-Type", "application/json")
-            .json(&FeiShuMessage {
-                msg_type: "text".to_string(),
-                content: FeiShuContent::Text {
-                    text: message.to_owned(),
-                },
-            })
+            .json(&body)
             .send()
-            .await?;
-
-        if res.status() != StatusCode::OK {
-            if let Ok(val) = res.text().await {
-                dbg!(&val);
+            .await
+            .map_err(|err| Error::Notification {
+                backend: BACKEND.to_string(),
+                source: err.into(),
+            })?;
 
-                return Err(Box::new(Error::new(val)));
-            }
+        let res = check_status(BACKEND, res).await?;
+        let text = res.text().await.unwrap_or_default();
+        match serde_json::from_str::<FeiShuResponse>(&text) {
+            Ok(FeiShuResponse {
+                code: Some(RATE_LIMITED),
+                ..
+            }) => Err(Error::NotificationRateLimited {
+                backend: BACKEND.to_string(),
+                retry_after: None,
+            }),
+            Ok(FeiShuResponse {
+                code: Some(code),
+                msg,
+            }) if code != 0 => Err(Error::NotificationRejected {
+                backend: BACKEND.to_string(),
+                message: format!("{} {}", code, msg.unwrap_or_default()),
+            }),
+            _ => Ok(()),
         }
+    }
+}
 
-        Ok(())
+/// Signature of a request sent at `timestamp`, the base64 HMAC-SHA256 of nothing keyed with
+/// `timestamp` and the secret separated by a newline
+fn sign(secret: &str, timestamp: u64) -> Result<String> {
+    let key = format!("{}\n{}", timestamp, secret);
+    let signature = PKey::hmac(key.as_bytes())
+        .and_then(|key| Signer::new(MessageDigest::sha256(), &key)?.sign_to_vec())
+        .map_err(|err| Error::Notification {
+            backend: BACKEND.to_string(),
+            source: err.into(),
+        })?;
+    Ok(openssl::base64::encode_block(&signature))
+}
+
+#[async_trait]
+impl Notifier for FeiShu {
+    fn name(&self) -> &str {
+        BACKEND
+    }
+
+    /// Custom bots allow 5 requests per second
+    fn rate_limit(&self) -> Duration {
+        Duration::from_millis(200)
+    }
+
+    async fn notify(&self, event: &Event) -> Result<()> {
+        let message = self.format.render(event);
+        if self.card || self.buttons(event).is_some() {
+            self.send_card(event, message).await
+        } else {
+            self.send(message).await
+        }
     }
 }
 
@@ -55,6 +274,9 @@ mod tests {
     use serde_json::json;
 
     use super::*;
+    use crate::notification::{AddedTorrent, EventKind};
+    use httpmock::prelude::*;
+    use std::collections::HashMap;
 
     #[tokio::test]
     async fn test_message() {
@@ -69,4 +291,140 @@ mod tests {
         let msg_str = serde_json::to_string(&msg).unwrap();
         assert!(msg_str == "{\"content\":{\"text\":\"test\"},\"msg_type\":\"text\"}");
     }
+
+    #[test]
+    fn test_send_card() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/hook").json_body(json!({
+                "msg_type": "interactive",
+                "card": {
+                    "header": {
+                        "title": { "tag": "plain_text", "content": "Torrent added" },
+                        "template": "green",
+                    },
+                    "elements": [{
+                        "tag": "div",
+                        "text": { "tag": "lark_md", "content": "**episode 1** in /downloads" },
+                    }],
+                },
+            }));
+            then.status(200).body(r#"{"code":0,"msg":"success"}"#);
+        });
+        let format = Format {
+            templates: HashMap::from([(
+                EventKind::Added,
+                "**{title}** in {download_dir}".to_string(),
+            )]),
+            parse_mode: None,
+        };
+        let notifier = FeiShu::new(server.url("/hook")).with_format(format, true);
+
+        let event = Event::Added {
+            feed: "anime".to_string(),
+            torrent: AddedTorrent {
+                title: "episode 1".to_string(),
+                download_dir: "/downloads".to_string(),
+                ..Default::default()
+            },
+        };
+        let result = tokio_test::block_on(notifier.notify(&event));
+
+        mock.assert();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_approval_buttons() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/hook").json_body(json!({
+                "msg_type": "interactive",
+                "card": {
+                    "header": {
+                        "title": { "tag": "plain_text", "content": "Torrent added" },
+                        "template": "green",
+                    },
+                    "elements": [
+                        {
+                            "tag": "div",
+                            "text": { "tag": "lark_md", "content": "Downloading: episode 1" },
+                        },
+                        {
+                            "tag": "action",
+                            "actions": [{
+                                "tag": "button",
+                                "text": { "tag": "plain_text", "content": "Remove" },
+                                "url": "https://rss.example.com/approval/remove/abc?token=secret",
+                                "type": "danger",
+                            }],
+                        },
+                    ],
+                },
+            }));
+            then.status(200).body(r#"{"code":0,"msg":"success"}"#);
+        });
+        // buttons are only sent in cards
+        let notifier = FeiShu::new(server.url("/hook"))
+            .with_callback_url(Some("https://rss.example.com/".to_string()));
+
+        let event = Event::Added {
+            feed: "anime".to_string(),
+            torrent: AddedTorrent {
+                title: "episode 1".to_string(),
+                info_hash: "abc".to_string(),
+                approval: Some("secret".to_string()),
+                ..Default::default()
+            },
+        };
+        let result = tokio_test::block_on(notifier.notify(&event));
+
+        mock.assert();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_sign() {
+        assert_eq!(
+            sign("demo", 1599360473).unwrap(),
+            "l1N0gAcBjdwBvGm1xMjOF0XSyaLRpR7tuO5dHfhAYc8="
+        );
+    }
+
+    #[test]
+    fn test_send_signed() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/hook")
+                .body_contains("\"timestamp\":\"")
+                .body_contains("\"sign\":\"");
+            then.status(200).body(r#"{"code":0,"msg":"success"}"#);
+        });
+        let notifier = FeiShu::new(server.url("/hook")).with_secret(Some("demo".to_string()));
+
+        let result = tokio_test::block_on(notifier.send("test".into()));
+
+        mock.assert();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_send_rejected() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/hook");
+            then.status(200)
+                .body(r#"{"code":19021,"msg":"sign match fail or timestamp is not within one hour from current time"}"#);
+        });
+        let notifier = FeiShu::new(server.url("/hook"));
+
+        let result = tokio_test::block_on(notifier.send("test".into()));
+
+        assert!(
+            matches!(&result, Err(Error::NotificationRejected { message, .. }) if message.starts_with("19021 ")),
+            "{:?}",
+            result
+        );
+    }
 }
@@ -0,0 +1,142 @@
+use crate::config::{GotifyNotification, Priority};
+use crate::error::{Error, Result};
+use crate::notification::delivery::check_status;
+use crate::notification::{Event, Notifier};
+use async_trait::async_trait;
+use serde::Serialize;
+
+const BACKEND: &str = "gotify";
+
+#[derive(Serialize)]
+struct Message {
+    title: String,
+    message: String,
+    priority: u8,
+}
+
+pub struct Gotify {
+    cfg: GotifyNotification,
+}
+
+impl Gotify {
+    pub fn new(cfg: GotifyNotification) -> Self {
+        Self { cfg }
+    }
+
+    pub async fn send(&self, event: &Event) -> Result<()> {
+        let priority = self
+            .cfg
+            .priority
+            .get(&event.kind())
+            .copied()
+            .unwrap_or_default();
+        let message = Message {
+            title: event.title().to_string(),
+            message: event.message(),
+            priority: gotify_priority(priority),
+        };
+
+        let client = reqwest::Client::new();
+        let res = client
+            .post(format!("{}/message", self.cfg.server.trim_end_matches('/')))
+            .header("X-Gotify-Key", &self.cfg.token)
+            .json(&message)
+            .send()
+            .await
+            .map_err(|err| Error::Notification {
+                backend: BACKEND.to_string(),
+                source: err.into(),
+            })?;
+
+        check_status(BACKEND, res).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Notifier for Gotify {
+    fn name(&self) -> &str {
+        BACKEND
+    }
+
+    async fn notify(&self, event: &Event) -> Result<()> {
+        self.send(event).await
+    }
+}
+
+// gotify clients treat 8 and above as high priority
+fn gotify_priority(priority: Priority) -> u8 {
+    match priority {
+        Priority::Min => 0,
+        Priority::Low => 2,
+        Priority::Default => 5,
+        Priority::High => 8,
+        Priority::Max => 10,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notification::AddedTorrent;
+    use httpmock::prelude::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_send() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/message")
+                .header("X-Gotify-Key", "app_token")
+                .json_body(json!({
+                    "title": "Torrent added",
+                    "message": "Downloading: episode 1",
+                    "priority": 2,
+                }));
+            then.status(200);
+        });
+        let cfg: GotifyNotification = toml::from_str(&format!(
+            "server = \"{}\"\ntoken = \"app_token\"\npriority = {{ added = \"low\" }}",
+            server.base_url()
+        ))
+        .unwrap();
+        let notifier = Gotify::new(cfg);
+
+        let event = Event::Added {
+            feed: "anime".to_string(),
+            torrent: AddedTorrent {
+                title: "episode 1".to_string(),
+                ..Default::default()
+            },
+        };
+        let result = tokio_test::block_on(notifier.send(&event));
+
+        mock.assert();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_send_rejected() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/message");
+            then.status(401)
+                .body(r#"{"error":"Unauthorized","errorCode":401}"#);
+        });
+        let cfg: GotifyNotification = toml::from_str(&format!(
+            "server = \"{}\"\ntoken = \"app_token\"",
+            server.base_url()
+        ))
+        .unwrap();
+        let notifier = Gotify::new(cfg);
+
+        let event = Event::TransmissionUnreachable {
+            error: "timeout".to_string(),
+        };
+        let result = tokio_test::block_on(notifier.send(&event));
+
+        assert!(matches!(result, Err(Error::NotificationRejected { .. })));
+    }
+}
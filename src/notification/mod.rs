@@ -1,49 +1,870 @@
+mod apprise;
+pub mod delivery;
+mod discord;
+mod email;
 mod feishu;
-mod telegram;
+mod gotify;
+mod ntfy;
+mod slack;
+pub mod telegram;
+pub mod template;
+mod webhook;
 
-use crate::config::Config;
-use std::error::Error as StdError;
-use std::fmt;
+use crate::config::{default_events, Notification};
+use crate::error::{Error, Result};
+use crate::retry::RetryPolicy;
+use async_trait::async_trait;
+use delivery::{OutboxEntry, OUTBOX_TREE};
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use template::Format;
 
-#[derive(Debug)]
-pub struct Error {
-    message: String,
+/// Kinds of [`Event`] a notifier can subscribe to
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Added,
+    ApprovalRequested,
+    FeedFailed,
+    TransmissionUnreachable,
+    Summary,
 }
 
-impl Error {
-    pub fn new(message: String) -> Self {
-        Self { message }
+impl EventKind {
+    /// Name used in the config
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::Added => "added",
+            EventKind::ApprovalRequested => "approval_requested",
+            EventKind::FeedFailed => "feed_failed",
+            EventKind::TransmissionUnreachable => "transmission_unreachable",
+            EventKind::Summary => "summary",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Event {
+    /// A torrent was added to transmission
+    Added { feed: String, torrent: AddedTorrent },
+    /// An item of an approval mode feed waits for approval
+    ApprovalRequested { feed: String, torrent: AddedTorrent },
+    /// A feed couldn't be fetched or processed
+    FeedFailed { feed: String, error: String },
+    /// Transmission couldn't be reached or rejected the credentials
+    TransmissionUnreachable { error: String },
+    /// End of a run with the outcome of every feed
+    Summary { feeds: Vec<FeedSummary> },
+}
+
+/// Details of an added torrent, available to message templates
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AddedTorrent {
+    /// Title of the feed item
+    pub title: String,
+    /// Total size in bytes, unknown for magnet links
+    pub size: Option<i64>,
+    /// Number of files, unknown for magnet links
+    pub files: Option<usize>,
+    pub info_hash: String,
+    pub download_dir: String,
+    /// Page of the feed item, the torrent or magnet link when the item has none
+    pub link: String,
+    /// Token of the approval record for items of approval mode feeds, see [`crate::approval`]
+    pub approval: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FeedSummary {
+    pub feed: String,
+    /// Torrents added, or the error that stopped the feed
+    pub result: std::result::Result<i32, String>,
+}
+
+impl Event {
+    /// Event for a failed feed, telling transmission failures apart
+    pub fn failure(feed: &str, err: &Error) -> Self {
+        if err.is_transmission() {
+            Event::TransmissionUnreachable {
+                error: err.to_string(),
+            }
+        } else {
+            Event::FeedFailed {
+                feed: feed.to_string(),
+                error: err.to_string(),
+            }
+        }
+    }
+
+    /// Title of the feed the event is about, if any
+    pub fn feed(&self) -> Option<&str> {
+        match self {
+            Event::Added { feed, .. }
+            | Event::ApprovalRequested { feed, .. }
+            | Event::FeedFailed { feed, .. } => Some(feed),
+            Event::TransmissionUnreachable { .. } | Event::Summary { .. } => None,
+        }
+    }
+
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::Added { .. } => EventKind::Added,
+            Event::ApprovalRequested { .. } => EventKind::ApprovalRequested,
+            Event::FeedFailed { .. } => EventKind::FeedFailed,
+            Event::TransmissionUnreachable { .. } => EventKind::TransmissionUnreachable,
+            Event::Summary { .. } => EventKind::Summary,
+        }
+    }
+
+    /// Variables available to message templates, see [`template`]
+    pub fn variables(&self) -> Vec<(&'static str, String)> {
+        let mut variables = vec![
+            ("event", self.kind().name().to_string()),
+            ("message", self.message()),
+        ];
+        match self {
+            Event::Added { feed, torrent } | Event::ApprovalRequested { feed, torrent } => {
+                variables.extend([
+                    ("feed", feed.clone()),
+                    ("title", torrent.title.clone()),
+                    (
+                        "size",
+                        torrent
+                            .size
+                            .map_or("unknown".to_string(), template::human_size),
+                    ),
+                    (
+                        "files",
+                        torrent
+                            .files
+                            .map_or("unknown".to_string(), |it| it.to_string()),
+                    ),
+                    ("info_hash", torrent.info_hash.clone()),
+                    ("download_dir", torrent.download_dir.clone()),
+                    ("link", torrent.link.clone()),
+                ])
+            }
+            Event::FeedFailed { feed, error } => {
+                variables.extend([("feed", feed.clone()), ("error", error.clone())])
+            }
+            Event::TransmissionUnreachable { error } => variables.push(("error", error.clone())),
+            Event::Summary { .. } => {}
+        }
+        variables
+    }
+
+    /// Short title for backends showing one next to the message
+    pub fn title(&self) -> &'static str {
+        match self {
+            Event::Added { .. } => "Torrent added",
+            Event::ApprovalRequested { .. } => "Approval needed",
+            Event::FeedFailed { .. } => "Feed failed",
+            Event::TransmissionUnreachable { .. } => "Transmission unreachable",
+            Event::Summary { .. } => "Run finished",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Event::Added { torrent, .. } => format!("Downloading: {}", torrent.title),
+            Event::ApprovalRequested { feed, torrent } => {
+                format!("Approve {} from {}?", torrent.title, feed)
+            }
+            Event::FeedFailed { feed, error } => {
+                format!("Failed to process {} feed: {}", feed, error)
+            }
+            Event::TransmissionUnreachable { error } => {
+                format!("Transmission unreachable: {}", error)
+            }
+            Event::Summary { feeds } => {
+                let added: i32 = feeds.iter().filter_map(|it| it.result.as_ref().ok()).sum();
+                let mut message = format!("Run finished, {} torrents added", added);
+                for it in feeds {
+                    match &it.result {
+                        Ok(count) => message.push_str(&format!("\n{}: {} added", it.feed, count)),
+                        Err(err) => message.push_str(&format!("\n{}: failed, {}", it.feed, err)),
+                    }
+                }
+                message
+            }
+        }
     }
 }
 
-impl StdError for Error {}
+/// A notification channel
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    /// Backend name used in logs
+    fn name(&self) -> &str;
+
+    async fn notify(&self, event: &Event) -> Result<()>;
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str(&self.message)
+    /// Whether `event` is for this notifier at all, the events it isn't subscribed to aside
+    fn accepts(&self, _event: &Event) -> bool {
+        true
     }
+
+    /// Minimum time between two messages, to stay below the rate limit of the backend
+    fn rate_limit(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    /// Take the events held back for batching, the registry sends them with
+    /// [`Notifier::send_batch`] at the end of a run
+    fn take_batch(&self) -> Vec<Event> {
+        Vec::new()
+    }
+
+    /// Send the events taken by [`Notifier::take_batch`] at once
+    async fn send_batch(&self, _events: &[Event]) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Builds a notifier from its config section, for backends living outside this crate
+pub type Factory = fn(&toml::Value) -> Result<Box<dyn Notifier>>;
+
+struct Subscription {
+    notifier: Box<dyn Notifier>,
+    events: Vec<EventKind>,
+    /// When the last message was sent, locked while sending to keep the rate limit, not while
+    /// waiting to retry so a failing backend doesn't hold up the other messages
+    last_sent: tokio::sync::Mutex<Option<Instant>>,
 }
 
-/// Send notification to all enabled
-pub async fn notify_all(cfg: Config, message: String) {
-    // Telegram
-    if let Some(bot_cfg) = cfg.notification.telegram {
-        let notifier = telegram::Telegram::new(
-            bot_cfg.bot_token,
-            bot_cfg.chat_id,
-            "https://api.telegram.org".into(),
+/// Every configured notifier along with the events it subscribes to
+#[derive(Default)]
+pub struct Registry {
+    subscriptions: Vec<Subscription>,
+    /// How failed notifications are retried before they go to the outbox
+    retry: RetryPolicy,
+    /// Db of the notifications that couldn't be delivered, see [`Registry::retry_outbox`]
+    outbox: Option<Db>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Registry::default()
+    }
+
+    /// Registry of the built-in backends configured in `cfg`
+    pub fn from_config(cfg: &Notification) -> Result<Self> {
+        let mut registry = Registry::new();
+        for bot_cfg in cfg.telegram.iter() {
+            let notifier = telegram::Telegram::from_config(bot_cfg, telegram::API.into());
+            for chat in notifier.split() {
+                registry.register(Box::new(chat), bot_cfg.events.clone());
+            }
+        }
+        for feishu_cfg in cfg.feishu.iter() {
+            let format = Format {
+                templates: feishu_cfg.templates.clone(),
+                parse_mode: None,
+            };
+            let notifier = feishu::FeiShu::new(feishu_cfg.webhook.clone())
+                .with_format(format, feishu_cfg.card)
+                .with_callback_url(feishu_cfg.callback_url.clone())
+                .with_secret(feishu_cfg.secret.clone());
+            registry.register(Box::new(notifier), feishu_cfg.events.clone());
+        }
+        for discord_cfg in cfg.discord.iter() {
+            let notifier = discord::Discord::new(discord_cfg.webhook.clone());
+            registry.register(Box::new(notifier), discord_cfg.events.clone());
+        }
+        for slack_cfg in cfg.slack.iter() {
+            let notifier = slack::Slack::new(slack_cfg.webhook.clone());
+            registry.register(Box::new(notifier), slack_cfg.events.clone());
+        }
+        for webhook_cfg in cfg.webhook.iter() {
+            let notifier = webhook::Webhook::new(
+                webhook_cfg.url.clone(),
+                webhook_cfg.body.clone(),
+                webhook_cfg.headers.clone(),
+            )?;
+            registry.register(Box::new(notifier), webhook_cfg.events.clone());
+        }
+        for email_cfg in cfg.email.iter() {
+            let notifier = email::Email::new(email_cfg)?;
+            registry.register(Box::new(notifier), email_cfg.events.clone());
+        }
+        for ntfy_cfg in cfg.ntfy.iter() {
+            let notifier = ntfy::Ntfy::new(ntfy_cfg.clone());
+            registry.register(Box::new(notifier), ntfy_cfg.events.clone());
+        }
+        for gotify_cfg in cfg.gotify.iter() {
+            let notifier = gotify::Gotify::new(gotify_cfg.clone());
+            registry.register(Box::new(notifier), gotify_cfg.events.clone());
+        }
+        for apprise_cfg in cfg.apprise.iter() {
+            let notifier = apprise::Apprise::new(apprise_cfg.clone());
+            registry.register(Box::new(notifier), apprise_cfg.events.clone());
+        }
+        for name in cfg.other.keys() {
+            log::warn!("Unknown notification backend: {}", name);
+        }
+        Ok(registry)
+    }
+
+    /// Like [`Registry::from_config`], building the sections of other backends with `factories`.
+    ///
+    /// A section can be a table or an array of tables and may set `events`.
+    pub fn from_config_with(
+        cfg: &Notification,
+        factories: &HashMap<String, Factory>,
+    ) -> Result<Self> {
+        let mut builtin = cfg.clone();
+        builtin
+            .other
+            .retain(|name, _| !factories.contains_key(name));
+        let mut registry = Registry::from_config(&builtin)?;
+
+        for (name, section) in cfg.other.iter() {
+            let factory = match factories.get(name) {
+                Some(val) => val,
+                None => continue,
+            };
+            let sections = match section {
+                toml::Value::Array(val) => val.clone(),
+                val => vec![val.clone()],
+            };
+            for section in sections.iter() {
+                let events = match section.get("events") {
+                    Some(val) => val.clone().try_into()?,
+                    None => default_events(),
+                };
+                registry.register(factory(section)?, events);
+            }
+        }
+        Ok(registry)
+    }
+
+    pub fn register(&mut self, notifier: Box<dyn Notifier>, events: Vec<EventKind>) {
+        self.subscriptions.push(Subscription {
+            notifier,
+            events,
+            last_sent: Default::default(),
+        });
+    }
+
+    /// Retry failed notifications with `retry`
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Keep the notifications that can't be delivered in the db, see [`Registry::retry_outbox`]
+    pub fn with_outbox(mut self, db: &Db) -> Self {
+        self.outbox = Some(db.clone());
+        self
+    }
+
+    /// Send an event to every notifier subscribed to it
+    pub async fn notify_all(&self, event: &Event) {
+        let kind = event.kind();
+        let tasks = self
+            .subscriptions
+            .iter()
+            .enumerate()
+            .filter(|(_, it)| it.events.contains(&kind) && it.notifier.accepts(event))
+            .map(|(index, it)| self.deliver(index, it, event));
+        futures::future::join_all(tasks).await;
+    }
+
+    /// Send the notifications left in the outbox by a previous run or poll
+    pub async fn retry_outbox(&self) -> Result<()> {
+        let outbox = match &self.outbox {
+            Some(val) => val.open_tree(OUTBOX_TREE)?,
+            None => return Ok(()),
+        };
+        // failing again puts the entry back in the tree, not to be read by this loop
+        let entries = outbox.iter().collect::<sled::Result<Vec<_>>>()?;
+        for (key, value) in entries {
+            // taken by a concurrent retry already
+            if outbox.remove(&key)?.is_none() {
+                continue;
+            }
+            let entry: OutboxEntry = match serde_json::from_slice(&value) {
+                Ok(val) => val,
+                Err(err) => {
+                    log::warn!("Dropping unreadable notification from the outbox: {}", err);
+                    continue;
+                }
+            };
+            match self.subscriptions.get(entry.index) {
+                Some(it) if it.notifier.name() == entry.notifier => {
+                    self.deliver(entry.index, it, &entry.event).await
+                }
+                _ => log::warn!(
+                    "Dropping {} notification from the outbox, the notifier is gone",
+                    entry.notifier
+                ),
+            }
+        }
+        outbox.flush_async().await?;
+        Ok(())
+    }
+
+    /// Send with retries, queueing the event in the outbox when it still fails
+    async fn deliver(&self, index: usize, sub: &Subscription, event: &Event) {
+        let events = std::slice::from_ref(event);
+        self.send(index, sub, events, || sub.notifier.notify(event))
+            .await
+    }
+
+    /// Call `send` with retries, queueing `events` in the outbox when it still fails
+    async fn send<F, Fut>(&self, index: usize, sub: &Subscription, events: &[Event], send: F)
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let name = sub.notifier.name();
+        let mut attempt = 0;
+        loop {
+            let res = {
+                let mut last_sent = sub.last_sent.lock().await;
+                if let Some(at) = *last_sent {
+                    tokio::time::sleep_until((at + sub.notifier.rate_limit()).into()).await;
+                }
+                let res = send().await;
+                *last_sent = Some(Instant::now());
+                res
+            };
+            attempt += 1;
+            let err = match res {
+                Ok(_) => {
+                    log::info!("{} notification sent!", name);
+                    return;
+                }
+                Err(err) => err,
+            };
+            match delivery::delay(&self.retry, &err, attempt) {
+                Some(delay) => {
+                    log::warn!("{}, retrying in {:?}", err, delay);
+                    tokio::time::sleep(delay).await;
+                }
+                None if delivery::is_retryable(&err) => {
+                    log::warn!("{}, giving up after {} attempts", err, attempt);
+                    for event in events {
+                        self.enqueue(index, name, event);
+                    }
+                    return;
+                }
+                None => {
+                    log::warn!("{}", err);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn enqueue(&self, index: usize, name: &str, event: &Event) {
+        let db = match &self.outbox {
+            Some(val) => val,
+            None => return,
+        };
+        let entry = OutboxEntry {
+            index,
+            notifier: name.to_string(),
+            event: event.clone(),
+        };
+        let value = serde_json::to_vec(&entry).expect("events always serialize");
+        // ids grow, big endian keeps the entries in order
+        let res = db.open_tree(OUTBOX_TREE).and_then(|outbox| {
+            let id = db.generate_id()?;
+            outbox.insert(id.to_be_bytes(), value)
+        });
+        match res {
+            Ok(_) => log::info!("{} notification kept in the outbox for the next run", name),
+            Err(err) => log::warn!("Failed to keep {} notification: {}", name, err),
+        }
+    }
+
+    /// Send the batched notifications with retries, see [`Notifier::take_batch`].
+    ///
+    /// A batch that can't be delivered goes to the outbox event by event, to be batched again.
+    pub async fn flush(&self) {
+        let tasks = self
+            .subscriptions
+            .iter()
+            .enumerate()
+            .map(|(index, it)| async move {
+                let events = it.notifier.take_batch();
+                if events.is_empty() {
+                    return;
+                }
+                self.send(index, it, &events, || it.notifier.send_batch(&events))
+                    .await
+            });
+        futures::future::join_all(tasks).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TelegramNotification;
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    struct Recorder {
+        name: String,
+        sent: &'static Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl Notifier for Recorder {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        async fn notify(&self, event: &Event) -> Result<()> {
+            self.sent
+                .lock()
+                .unwrap()
+                .push(format!("{}: {}", self.name, event.message()));
+            Ok(())
+        }
+    }
+
+    fn recorder(section: &toml::Value) -> Result<Box<dyn Notifier>> {
+        Ok(Box::new(Recorder {
+            name: section["name"].as_str().unwrap_or_default().to_string(),
+            sent: &FACTORY_SENT,
+        }))
+    }
+
+    static FACTORY_SENT: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    #[test]
+    fn test_summary_message() {
+        let event = Event::Summary {
+            feeds: vec![
+                FeedSummary {
+                    feed: "anime".to_string(),
+                    result: Ok(2),
+                },
+                FeedSummary {
+                    feed: "linux".to_string(),
+                    result: Err("timeout".to_string()),
+                },
+            ],
+        };
+        assert_eq!(
+            event.message(),
+            "Run finished, 2 torrents added\nanime: 2 added\nlinux: failed, timeout"
+        );
+    }
+
+    #[test]
+    fn test_events_config() {
+        let telegram: TelegramNotification =
+            toml::from_str("bot_token = \"token\"\nchat_id = 1").unwrap();
+        assert!(telegram.events.contains(&EventKind::FeedFailed));
+        assert!(!telegram.events.contains(&EventKind::Summary));
+
+        let telegram: TelegramNotification =
+            toml::from_str("bot_token = \"token\"\nchat_id = 1\nevents = [\"summary\"]").unwrap();
+        assert_eq!(telegram.events, vec![EventKind::Summary]);
+    }
+
+    #[tokio::test]
+    async fn test_registry() {
+        static SENT: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        let mut registry = Registry::new();
+        for (name, events) in [
+            ("a", vec![EventKind::Added]),
+            ("b", vec![EventKind::Summary]),
+        ] {
+            let notifier = Recorder {
+                name: name.to_string(),
+                sent: &SENT,
+            };
+            registry.register(Box::new(notifier), events);
+        }
+
+        let event = Event::Added {
+            feed: "anime".to_string(),
+            torrent: AddedTorrent {
+                title: "episode 1".to_string(),
+                ..Default::default()
+            },
+        };
+        registry.notify_all(&event).await;
+        assert_eq!(*SENT.lock().unwrap(), vec!["a: Downloading: episode 1"]);
+    }
+
+    #[tokio::test]
+    async fn test_registry_from_config() {
+        let cfg: Notification = toml::from_str(
+            r#"
+[[telegram]]
+bot_token = "token"
+chat_id = 1
+
+[[telegram]]
+bot_token = "token"
+chat_id = 2
+events = ["summary"]
+
+[feishu]
+webhook = "https://example.com/hook"
+secret = "demo"
+
+[[recorder]]
+name = "first"
+
+[[recorder]]
+name = "second"
+events = ["summary"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(cfg.telegram.len(), 2);
+        assert_eq!(cfg.telegram[1].events, vec![EventKind::Summary]);
+        assert_eq!(cfg.feishu.len(), 1);
+        assert_eq!(cfg.feishu[0].secret.as_deref(), Some("demo"));
+
+        let mut factories: HashMap<String, Factory> = HashMap::new();
+        factories.insert("recorder".to_string(), recorder);
+        let registry = Registry::from_config_with(&cfg, &factories).unwrap();
+        assert_eq!(registry.subscriptions.len(), 5);
+
+        // only the custom backends, the others would hit the network
+        let registry = Registry {
+            subscriptions: registry
+                .subscriptions
+                .into_iter()
+                .filter(|it| it.notifier.name() != "telegram" && it.notifier.name() != "feishu")
+                .collect(),
+            ..Default::default()
+        };
+        registry.notify_all(&Event::Summary { feeds: vec![] }).await;
+        assert_eq!(
+            *FACTORY_SENT.lock().unwrap(),
+            vec!["second: Run finished, 0 torrents added"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_registry_retry() {
+        let server = httpmock::MockServer::start_async().await;
+        let hook = server
+            .mock_async(|when, then| {
+                when.method(httpmock::Method::POST).path("/hook");
+                then.status(503);
+            })
+            .await;
+        let cfg: crate::config::Config = toml::from_str(&format!(
+            r#"
+rss_list = []
+
+[persistence]
+path = "./test"
+
+[transmission]
+url = "http://127.0.0.1:1/transmission/rpc"
+username = "user"
+password = "pass"
+
+[retry]
+max_attempts = 2
+base_delay = 0.001
+jitter = 0.0
+
+[[notification.webhook]]
+url = "{}"
+body = "{{message}}"
+"#,
+            server.url("/hook")
+        ))
+        .unwrap();
+        let registry = Registry::from_config(&cfg.notification)
+            .unwrap()
+            .with_retry(cfg.retry.clone());
+
+        let event = Event::FeedFailed {
+            feed: "anime".to_string(),
+            error: "timeout".to_string(),
+        };
+        registry.notify_all(&event).await;
+        // the configured attempts, not the default ones
+        hook.assert_hits_async(2).await;
+    }
+
+    /// Fails with a transport error while `failures` is above zero
+    struct Flaky {
+        failures: Arc<AtomicU32>,
+        sent: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Notifier for Flaky {
+        fn name(&self) -> &str {
+            "flaky"
+        }
+
+        async fn notify(&self, event: &Event) -> Result<()> {
+            let failures = self.failures.load(Ordering::SeqCst);
+            if failures > 0 {
+                self.failures.store(failures - 1, Ordering::SeqCst);
+                return Err(Error::Notification {
+                    backend: "flaky".to_string(),
+                    source: "connection reset".into(),
+                });
+            }
+            self.sent.lock().unwrap().push(event.message());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_and_outbox() {
+        let failures = Arc::new(AtomicU32::new(1));
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let mut registry = Registry::new()
+            .with_retry(RetryPolicy {
+                max_attempts: 2,
+                base_delay: 0.001,
+                jitter: 0.0,
+                ..Default::default()
+            })
+            .with_outbox(&db);
+        let notifier = Flaky {
+            failures: failures.clone(),
+            sent: sent.clone(),
+        };
+        registry.register(Box::new(notifier), vec![EventKind::FeedFailed]);
+        let event = |feed: &str| Event::FeedFailed {
+            feed: feed.to_string(),
+            error: "timeout".to_string(),
+        };
+
+        // the second attempt goes through
+        registry.notify_all(&event("anime")).await;
+        assert_eq!(sent.lock().unwrap().len(), 1);
+
+        // every attempt fails, kept for the next run
+        failures.store(2, Ordering::SeqCst);
+        registry.notify_all(&event("linux")).await;
+        let outbox = db.open_tree(OUTBOX_TREE).unwrap();
+        assert_eq!(outbox.len(), 1);
+
+        registry.retry_outbox().await.unwrap();
+        assert!(outbox.is_empty());
+        assert_eq!(
+            *sent.lock().unwrap(),
+            vec![
+                "Failed to process anime feed: timeout",
+                "Failed to process linux feed: timeout"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retry_doesnt_block() {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let mut registry = Registry::new().with_retry(RetryPolicy {
+            max_attempts: 2,
+            base_delay: 0.2,
+            jitter: 0.0,
+            ..Default::default()
+        });
+        let notifier = Flaky {
+            failures: Arc::new(AtomicU32::new(1)),
+            sent: sent.clone(),
+        };
+        registry.register(Box::new(notifier), vec![EventKind::FeedFailed]);
+        let event = |feed: &str| Event::FeedFailed {
+            feed: feed.to_string(),
+            error: "timeout".to_string(),
+        };
+
+        // linux is sent while anime waits to be retried
+        let (anime, linux) = (event("anime"), event("linux"));
+        tokio::join!(registry.notify_all(&anime), registry.notify_all(&linux));
+        assert_eq!(
+            *sent.lock().unwrap(),
+            vec![
+                "Failed to process linux feed: timeout",
+                "Failed to process anime feed: timeout"
+            ]
         );
-        match notifier.send(message.clone()).await {
-            Ok(_) => println!("Telegram notification sent!"),
-            Err(err) => println!("Failed to send telegram message: {}", err),
+    }
+
+    /// Batches every event, the batch fails while `fail` is set
+    struct Batching {
+        fail: Arc<AtomicBool>,
+        pending: Mutex<Vec<Event>>,
+        sent: Arc<Mutex<Vec<usize>>>,
+    }
+
+    #[async_trait]
+    impl Notifier for Batching {
+        fn name(&self) -> &str {
+            "batching"
+        }
+
+        async fn notify(&self, event: &Event) -> Result<()> {
+            self.pending.lock().unwrap().push(event.clone());
+            Ok(())
+        }
+
+        fn take_batch(&self) -> Vec<Event> {
+            std::mem::take(&mut *self.pending.lock().unwrap())
+        }
+
+        async fn send_batch(&self, events: &[Event]) -> Result<()> {
+            if self.fail.load(Ordering::SeqCst) {
+                return Err(Error::Notification {
+                    backend: "batching".to_string(),
+                    source: "timeout".into(),
+                });
+            }
+            self.sent.lock().unwrap().push(events.len());
+            Ok(())
         }
     }
 
-    if let Some(feishu_cfg) = cfg.notification.feishu {
-        let notifier = feishu::FeiShu::new(feishu_cfg.webhook);
-        match notifier.send(message.clone()).await {
-            Ok(_) => println!("Feishu notification sent!"),
-            Err(err) => println!("Failed to send feishu message: {}", err),
+    #[tokio::test]
+    async fn test_flush_outbox() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let fail = Arc::new(AtomicBool::new(true));
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let notifier = Batching {
+            fail: fail.clone(),
+            pending: Default::default(),
+            sent: sent.clone(),
+        };
+        let mut registry = Registry::new()
+            .with_retry(RetryPolicy {
+                max_attempts: 2,
+                base_delay: 0.001,
+                jitter: 0.0,
+                ..Default::default()
+            })
+            .with_outbox(&db);
+        registry.register(Box::new(notifier), vec![EventKind::FeedFailed]);
+        for feed in ["anime", "linux"] {
+            let event = Event::FeedFailed {
+                feed: feed.to_string(),
+                error: "timeout".to_string(),
+            };
+            registry.notify_all(&event).await;
         }
+
+        registry.flush().await;
+        let outbox = db.open_tree(OUTBOX_TREE).unwrap();
+        assert_eq!(outbox.len(), 2);
+
+        // batched again by the retry and sent with the next flush
+        fail.store(false, Ordering::SeqCst);
+        registry.retry_outbox().await.unwrap();
+        registry.flush().await;
+        assert!(outbox.is_empty());
+        assert_eq!(*sent.lock().unwrap(), vec![2]);
     }
 }
@@ -0,0 +1,146 @@
+use crate::config::{NtfyNotification, Priority};
+use crate::error::{Error, Result};
+use crate::notification::delivery::check_status;
+use crate::notification::{Event, Notifier};
+use async_trait::async_trait;
+use serde::Serialize;
+
+const BACKEND: &str = "ntfy";
+
+#[derive(Serialize)]
+struct Message {
+    topic: String,
+    title: String,
+    message: String,
+    priority: u8,
+}
+
+pub struct Ntfy {
+    cfg: NtfyNotification,
+}
+
+impl Ntfy {
+    pub fn new(cfg: NtfyNotification) -> Self {
+        Self { cfg }
+    }
+
+    pub async fn send(&self, event: &Event) -> Result<()> {
+        let priority = self
+            .cfg
+            .priority
+            .get(&event.kind())
+            .copied()
+            .unwrap_or_default();
+        let message = Message {
+            topic: self.cfg.topic.clone(),
+            title: event.title().to_string(),
+            message: event.message(),
+            priority: ntfy_priority(priority),
+        };
+
+        let client = reqwest::Client::new();
+        // JSON messages are published to the root url, the topic is part of the body
+        let mut req = client
+            .post(self.cfg.server.trim_end_matches('/'))
+            .json(&message);
+        if let Some(token) = &self.cfg.token {
+            req = req.bearer_auth(token);
+        }
+        let res = req.send().await.map_err(|err| Error::Notification {
+            backend: BACKEND.to_string(),
+            source: err.into(),
+        })?;
+
+        check_status(BACKEND, res).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Notifier for Ntfy {
+    fn name(&self) -> &str {
+        BACKEND
+    }
+
+    async fn notify(&self, event: &Event) -> Result<()> {
+        self.send(event).await
+    }
+}
+
+fn ntfy_priority(priority: Priority) -> u8 {
+    match priority {
+        Priority::Min => 1,
+        Priority::Low => 2,
+        Priority::Default => 3,
+        Priority::High => 4,
+        Priority::Max => 5,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notification::AddedTorrent;
+    use httpmock::prelude::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_send() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/")
+                .header("Authorization", "Bearer tk_secret")
+                .json_body(json!({
+                    "topic": "torrents",
+                    "title": "Feed failed",
+                    "message": "Failed to process anime feed: timeout",
+                    "priority": 4,
+                }));
+            then.status(200);
+        });
+        let cfg: NtfyNotification = toml::from_str(&format!(
+            "server = \"{}\"\ntopic = \"torrents\"\ntoken = \"tk_secret\"",
+            server.url("/")
+        ))
+        .unwrap();
+        let notifier = Ntfy::new(cfg);
+
+        let event = Event::FeedFailed {
+            feed: "anime".to_string(),
+            error: "timeout".to_string(),
+        };
+        let result = tokio_test::block_on(notifier.send(&event));
+
+        mock.assert();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_send_rejected() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/");
+            then.status(403)
+                .body(r#"{"code":40301,"http":403,"error":"forbidden"}"#);
+        });
+        let cfg: NtfyNotification = toml::from_str(&format!(
+            "server = \"{}\"\ntopic = \"torrents\"",
+            server.url("/")
+        ))
+        .unwrap();
+        let notifier = Ntfy::new(cfg);
+
+        let event = Event::Added {
+            feed: "anime".to_string(),
+            torrent: AddedTorrent {
+                title: "episode 1".to_string(),
+                ..Default::default()
+            },
+        };
+        let result = tokio_test::block_on(notifier.send(&event));
+
+        assert!(matches!(result, Err(Error::NotificationRejected { .. })));
+    }
+}
@@ -0,0 +1,95 @@
+use crate::error::{Error, Result};
+use crate::notification::delivery::check_status;
+use crate::notification::{Event, Notifier};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::time::Duration;
+
+const BACKEND: &str = "slack";
+
+#[derive(Serialize)]
+struct Message {
+    text: String,
+}
+
+pub struct Slack {
+    webhook: String,
+}
+
+impl Slack {
+    pub fn new(webhook: String) -> Self {
+        Self { webhook }
+    }
+
+    pub async fn send(&self, message: String) -> Result<()> {
+        let client = reqwest::Client::new();
+        let res = client
+            .post(&self.webhook)
+            .json(&Message { text: message })
+            .send()
+            .await
+            .map_err(|err| Error::Notification {
+                backend: BACKEND.to_string(),
+                source: err.into(),
+            })?;
+
+        // errors come back as a plain text reason such as `invalid_payload`
+        check_status(BACKEND, res).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Notifier for Slack {
+    fn name(&self) -> &str {
+        BACKEND
+    }
+
+    /// Incoming webhooks allow one message per second
+    fn rate_limit(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+
+    async fn notify(&self, event: &Event) -> Result<()> {
+        self.send(event.message()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_send() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/services/T000/B000/XXXX")
+                .json_body(json!({ "text": "test message" }));
+            then.status(200).body("ok");
+        });
+        let notifier = Slack::new(server.url("/services/T000/B000/XXXX"));
+
+        let result = tokio_test::block_on(notifier.send("test message".into()));
+
+        mock.assert();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_send_rejected() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/services/T000/B000/XXXX");
+            then.status(403).body("invalid_token");
+        });
+        let notifier = Slack::new(server.url("/services/T000/B000/XXXX"));
+
+        let result = tokio_test::block_on(notifier.send("test message".into()));
+
+        assert!(matches!(result, Err(Error::NotificationRejected { .. })));
+    }
+}
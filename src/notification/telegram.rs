@@ -1,63 +1,367 @@
-use serde::Serialize;
-use std::error::Error as StdError;
+use serde::{Deserialize, Serialize};
 
-use reqwest::StatusCode;
+use crate::approval::Action;
+use crate::config::{ParseMode, TelegramNotification, TelegramTarget};
+use crate::error::{Error, Result};
+use crate::notification::delivery::check_status;
+use crate::notification::template::Format;
+use crate::notification::{Event, Notifier};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::time::Duration;
 
-use super::Error;
+const BACKEND: &str = "telegram";
+/// Base url of the Bot API
+pub const API: &str = "https://api.telegram.org";
+
+#[derive(Deserialize)]
+struct ApiResponse<T> {
+    ok: bool,
+    result: Option<T>,
+    description: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Update {
+    pub update_id: i64,
+    pub message: Option<IncomingMessage>,
+    pub callback_query: Option<CallbackQuery>,
+}
+
+/// Press of an inline keyboard button
+#[derive(Deserialize, Debug)]
+pub struct CallbackQuery {
+    pub id: String,
+    /// `callback_data` of the button
+    pub data: Option<String>,
+    /// Message the button belongs to
+    pub message: Option<IncomingMessage>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct IncomingMessage {
+    pub chat: Chat,
+    /// Topic of the message in forum supergroups
+    pub message_thread_id: Option<i64>,
+    pub text: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Chat {
+    pub id: i64,
+}
 
 #[derive(Serialize)]
 struct Message {
     chat_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message_thread_id: Option<i64>,
     text: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    disable_notification: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parse_mode: Option<ParseMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_markup: Option<ReplyMarkup>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct ReplyMarkup {
+    inline_keyboard: Vec<Vec<InlineButton>>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct InlineButton {
+    text: String,
+    /// `<action>:<info-hash>`, see [`crate::approval::Action`]
+    callback_data: String,
+}
+
+#[derive(Serialize)]
+struct CallbackAnswer<'a> {
+    callback_query_id: &'a str,
+    text: &'a str,
 }
 
 pub struct Telegram {
     base_url: String,
-    chat_id: i64,
+    /// Chats receiving the events
+    targets: Vec<TelegramTarget>,
+    /// Chats receiving the events of a feed instead of `targets`, by feed title
+    feeds: HashMap<String, Vec<TelegramTarget>>,
     bot_token: String,
+    format: Format,
+    /// Attach approval buttons, answered by the bot in daemon mode
+    buttons: bool,
 }
 
 impl Telegram {
     pub fn new(bot_token: String, chat_id: i64, base_url: String) -> Self {
         Self {
-            chat_id,
+            targets: vec![TelegramTarget {
+                chat_id,
+                ..Default::default()
+            }],
+            feeds: HashMap::new(),
             bot_token,
             base_url,
+            format: Format::default(),
+            buttons: false,
+        }
+    }
+
+    /// Notifier of a config section, answering the approval buttons with `commands`
+    pub fn from_config(cfg: &TelegramNotification, base_url: String) -> Self {
+        Self {
+            targets: cfg.targets.clone(),
+            feeds: cfg.feeds.clone(),
+            bot_token: cfg.bot_token.clone(),
+            base_url,
+            format: Format {
+                templates: cfg.templates.clone(),
+                parse_mode: cfg.parse_mode,
+            },
+            buttons: cfg.commands,
+        }
+    }
+
+    /// One notifier per chat, so the registry retries and keeps in the outbox only the events of
+    /// the chat that failed
+    pub fn split(self) -> Vec<Telegram> {
+        let mut chats: Vec<&TelegramTarget> = Vec::new();
+        for target in self.targets.iter().chain(self.feeds.values().flatten()) {
+            if !chats.contains(&target) {
+                chats.push(target);
+            }
         }
+        let only = |targets: &Vec<TelegramTarget>, chat: &TelegramTarget| {
+            targets.iter().filter(|it| *it == chat).cloned().collect()
+        };
+        chats
+            .into_iter()
+            .map(|chat| Telegram {
+                base_url: self.base_url.clone(),
+                targets: only(&self.targets, chat),
+                // feeds without this chat are kept, their events mustn't fall back to `targets`
+                feeds: (self.feeds.iter())
+                    .map(|(feed, targets)| (feed.clone(), only(targets, chat)))
+                    .collect(),
+                bot_token: self.bot_token.clone(),
+                format: self.format.clone(),
+                buttons: self.buttons,
+            })
+            .collect()
     }
 
-    pub async fn send(&self, message: String) -> Result<(), Box<dyn StdError>> {
+    /// Render events with templates, `send` expects text in the markup of `format`
+    pub fn with_format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Attach approve/reject buttons to approval requests and a remove button to approved torrents
+    pub fn with_buttons(mut self, buttons: bool) -> Self {
+        self.buttons = buttons;
+        self
+    }
+
+    /// Whether `chat_id` is one of the chats of the bot, commands are only accepted from those
+    pub fn is_target(&self, chat_id: i64) -> bool {
+        self.targets
+            .iter()
+            .chain(self.feeds.values().flatten())
+            .any(|it| it.chat_id == chat_id)
+    }
+
+    /// Chats receiving `event`
+    fn targets_of(&self, event: &Event) -> &[TelegramTarget] {
+        event
+            .feed()
+            .and_then(|feed| self.feeds.get(feed))
+            .unwrap_or(&self.targets)
+    }
+
+    /// Long poll the messages sent to the bot, `timeout` in seconds
+    pub async fn get_updates(&self, offset: i64, timeout: u64) -> Result<Vec<Update>> {
+        let client = reqwest::Client::new();
+        let res = client
+            .get(format!(
+                "{}/bot{}/getUpdates",
+                self.base_url, self.bot_token
+            ))
+            .query(&[
+                ("offset", offset.to_string()),
+                ("timeout", timeout.to_string()),
+                (
+                    "allowed_updates",
+                    "[\"message\",\"callback_query\"]".to_string(),
+                ),
+            ])
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|err| Error::Notification {
+                backend: BACKEND.to_string(),
+                source: err.into(),
+            })?;
+        let body: ApiResponse<Vec<Update>> =
+            res.json().await.map_err(|err| Error::Notification {
+                backend: BACKEND.to_string(),
+                source: err.into(),
+            })?;
+        match body {
+            ApiResponse {
+                ok: true,
+                result: Some(updates),
+                ..
+            } => Ok(updates),
+            ApiResponse { description, .. } => Err(Error::NotificationRejected {
+                backend: BACKEND.to_string(),
+                message: description.unwrap_or_default(),
+            }),
+        }
+    }
+
+    /// Send `message` to every target
+    pub async fn send(&self, message: String) -> Result<()> {
+        for target in self.targets.iter() {
+            self.send_message(target, message.clone(), None).await?;
+        }
+        Ok(())
+    }
+
+    /// Answer `message` with plain text, in its chat and topic
+    pub async fn reply(&self, message: &IncomingMessage, text: String) -> Result<()> {
+        self.post(
+            "sendMessage",
+            &Message {
+                chat_id: message.chat.id,
+                message_thread_id: message.message_thread_id,
+                text,
+                disable_notification: false,
+                parse_mode: None,
+                reply_markup: None,
+            },
+        )
+        .await
+    }
+
+    /// Show `text` to the user who pressed a button
+    pub async fn answer_callback_query(&self, id: &str, text: &str) -> Result<()> {
+        self.post(
+            "answerCallbackQuery",
+            &CallbackAnswer {
+                callback_query_id: id,
+                text,
+            },
+        )
+        .await
+    }
+
+    async fn send_message(
+        &self,
+        target: &TelegramTarget,
+        message: String,
+        reply_markup: Option<ReplyMarkup>,
+    ) -> Result<()> {
         let tel_msg = Message {
-            chat_id: self.chat_id.to_owned(),
-            text: message.to_owned(),
+            chat_id: target.chat_id,
+            message_thread_id: target.message_thread_id,
+            text: message,
+            disable_notification: target.disable_notification,
+            parse_mode: target.parse_mode.or(self.format.parse_mode),
+            reply_markup,
         };
+        self.post("sendMessage", &tel_msg).await
+    }
 
+    async fn post<T: Serialize>(&self, method: &str, body: &T) -> Result<()> {
         let client = reqwest::Client::new();
         let res = client
             .post(format!(
-                "{}/bot{}/sendMessage",
-                self.base_url, self.bot_token
+                "{}/bot{}/{}",
+                self.base_url, self.bot_token, method
             ))
-            .json(&tel_msg)
+            .json(body)
             .send()
-            .await?;
+            .await
+            .map_err(|err| Error::Notification {
+                backend: BACKEND.to_string(),
+                source: err.into(),
+            })?;
+
+        check_status(BACKEND, res).await?;
 
-        if res.status() != StatusCode::OK {
-            if let Ok(val) = res.text().await {
-                dbg!(&val);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Notifier for Telegram {
+    fn name(&self) -> &str {
+        BACKEND
+    }
+
+    /// Telegram allows about one message per second in a chat
+    fn rate_limit(&self) -> Duration {
+        Duration::from_secs(1)
+    }
 
-                return Err(Box::new(Error::new(val)));
+    fn accepts(&self, event: &Event) -> bool {
+        !self.targets_of(event).is_empty()
+    }
+
+    async fn notify(&self, event: &Event) -> Result<()> {
+        // keep going when a chat fails, the first error is reported
+        let mut result = Ok(());
+        for target in self.targets_of(event) {
+            let parse_mode = target.parse_mode.or(self.format.parse_mode);
+            let message = self.format.render_with(event, parse_mode);
+            let markup = if self.buttons { buttons(event) } else { None };
+            let res = self.send_message(target, message, markup).await;
+            if result.is_ok() {
+                result = res;
             }
         }
+        result
+    }
+}
+
+/// Approval buttons of an event, if any
+fn buttons(event: &Event) -> Option<ReplyMarkup> {
+    let row = match event {
+        Event::ApprovalRequested { torrent, .. } => vec![
+            button("Approve", Action::Approve, &torrent.info_hash),
+            button("Reject", Action::Reject, &torrent.info_hash),
+        ],
+        Event::Added { torrent, .. } if torrent.approval.is_some() => {
+            vec![button("Remove", Action::Remove, &torrent.info_hash)]
+        }
+        _ => return None,
+    };
+    Some(ReplyMarkup {
+        inline_keyboard: vec![row],
+    })
+}
 
-        Ok(())
+fn button(text: &str, action: Action, info_hash: &str) -> InlineButton {
+    InlineButton {
+        text: text.to_string(),
+        callback_data: format!("{}:{}", action.name(), info_hash),
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::config::{ParseMode, TelegramNotification};
+    use crate::error::Error;
     use crate::notification::telegram::Telegram;
+    use crate::notification::template::Format;
+    use crate::notification::{AddedTorrent, Event, EventKind, Notifier, Registry};
+    use crate::retry::RetryPolicy;
     use httpmock::prelude::*;
+    use serde_json::json;
+    use std::collections::HashMap;
 
     #[test]
     fn test_send() {
@@ -73,4 +377,236 @@ mod tests {
         mock.assert();
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_send_rejected() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/bot123token123/sendMessage");
+            then.status(400).body("Bad Request: chat not found");
+        });
+        let notifier = Telegram::new("123token123".into(), 123, server.url(""));
+
+        let result = tokio_test::block_on(notifier.send("test message".into()));
+
+        assert!(matches!(result, Err(Error::NotificationRejected { .. })));
+    }
+
+    #[test]
+    fn test_send_rate_limited() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/bot123token123/sendMessage");
+            then.status(429).json_body(json!({
+                "ok": false,
+                "error_code": 429,
+                "description": "Too Many Requests: retry after 3",
+                "parameters": { "retry_after": 3 },
+            }));
+        });
+        let notifier = Telegram::new("123token123".into(), 123, server.url(""));
+
+        let result = tokio_test::block_on(notifier.send("test message".into()));
+
+        assert!(matches!(
+            result,
+            Err(Error::NotificationRateLimited { retry_after: Some(val), .. })
+                if val == std::time::Duration::from_secs(3)
+        ));
+    }
+
+    #[test]
+    fn test_notify_html() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/bot123token123/sendMessage")
+                .json_body(json!({
+                    "chat_id": 123,
+                    "text": "<b>Tom &amp; Jerry</b> (unknown)",
+                    "parse_mode": "HTML",
+                }));
+            then.status(200);
+        });
+        let format = Format {
+            templates: HashMap::from([(EventKind::Added, "<b>{title}</b> ({size})".to_string())]),
+            parse_mode: Some(ParseMode::Html),
+        };
+        let notifier = Telegram::new("123token123".into(), 123, server.url("")).with_format(format);
+
+        let event = Event::Added {
+            feed: "cartoons".to_string(),
+            torrent: AddedTorrent {
+                title: "Tom & Jerry".to_string(),
+                ..Default::default()
+            },
+        };
+        let result = tokio_test::block_on(notifier.notify(&event));
+
+        mock.assert();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_approval_buttons() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/bot123token123/sendMessage")
+                .json_body(json!({
+                    "chat_id": 123,
+                    "text": "Approve Tom & Jerry from cartoons?",
+                    "reply_markup": {
+                        "inline_keyboard": [[
+                            { "text": "Approve", "callback_data": "approve:abc" },
+                            { "text": "Reject", "callback_data": "reject:abc" },
+                        ]],
+                    },
+                }));
+            then.status(200);
+        });
+        let notifier = Telegram::new("123token123".into(), 123, server.url("")).with_buttons(true);
+
+        let event = Event::ApprovalRequested {
+            feed: "cartoons".to_string(),
+            torrent: AddedTorrent {
+                title: "Tom & Jerry".to_string(),
+                info_hash: "abc".to_string(),
+                approval: Some("token".to_string()),
+                ..Default::default()
+            },
+        };
+        let result = tokio_test::block_on(notifier.notify(&event));
+
+        mock.assert();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_feed_targets() {
+        let server = MockServer::start();
+        let topic = server.mock(|when, then| {
+            when.method(POST)
+                .path("/bot123token123/sendMessage")
+                .json_body(json!({
+                    "chat_id": -100,
+                    "message_thread_id": 3,
+                    "text": "Downloading: episode 1",
+                    "disable_notification": true,
+                }));
+            then.status(200);
+        });
+        let default = server.mock(|when, then| {
+            when.method(POST)
+                .path("/bot123token123/sendMessage")
+                .json_body(
+                    json!({ "chat_id": 123, "text": "Failed to process linux feed: timeout" }),
+                );
+            then.status(200);
+        });
+        let cfg: TelegramNotification = toml::from_str(
+            r#"
+bot_token = "123token123"
+chat_id = 123
+
+[[feeds.anime]]
+chat_id = -100
+message_thread_id = 3
+disable_notification = true
+"#,
+        )
+        .unwrap();
+        let notifier = Telegram::from_config(&cfg, server.url(""));
+
+        let added = Event::Added {
+            feed: "anime".to_string(),
+            torrent: AddedTorrent {
+                title: "episode 1".to_string(),
+                ..Default::default()
+            },
+        };
+        let failed = Event::FeedFailed {
+            feed: "linux".to_string(),
+            error: "timeout".to_string(),
+        };
+        tokio_test::block_on(notifier.notify(&added)).unwrap();
+        tokio_test::block_on(notifier.notify(&failed)).unwrap();
+
+        topic.assert();
+        default.assert();
+    }
+
+    #[tokio::test]
+    async fn test_split_retries_failed_chat() {
+        let server = MockServer::start_async().await;
+        let ok = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/bot123token123/sendMessage")
+                    .json_body_partial(r#"{ "chat_id": 1 }"#);
+                then.status(200);
+            })
+            .await;
+        let failing = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/bot123token123/sendMessage")
+                    .json_body_partial(r#"{ "chat_id": 2 }"#);
+                then.status(500);
+            })
+            .await;
+        let cfg: TelegramNotification = toml::from_str(
+            r#"
+bot_token = "123token123"
+targets = [{ chat_id = 1 }, { chat_id = 2 }]
+
+[[feeds.anime]]
+chat_id = 2
+"#,
+        )
+        .unwrap();
+        let chats = Telegram::from_config(&cfg, server.url("")).split();
+        assert_eq!(chats.len(), 2);
+        assert!(!chats[0].accepts(&Event::FeedFailed {
+            feed: "anime".to_string(),
+            error: "timeout".to_string(),
+        }));
+
+        let mut registry = Registry::new().with_retry(RetryPolicy {
+            max_attempts: 2,
+            base_delay: 0.001,
+            ..Default::default()
+        });
+        for chat in chats {
+            registry.register(Box::new(chat), vec![EventKind::FeedFailed]);
+        }
+        let failed = Event::FeedFailed {
+            feed: "linux".to_string(),
+            error: "timeout".to_string(),
+        };
+        registry.notify_all(&failed).await;
+
+        assert_eq!(ok.hits_async().await, 1);
+        assert_eq!(failing.hits_async().await, 2);
+    }
+
+    #[test]
+    fn test_targets_config() {
+        let cfg: TelegramNotification = toml::from_str(
+            r#"
+bot_token = "token"
+chat_id = 1
+targets = [{ chat_id = 2, parse_mode = "HTML" }]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.targets.iter().map(|it| it.chat_id).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(cfg.targets[1].parse_mode, Some(ParseMode::Html));
+
+        let err = toml::from_str::<TelegramNotification>("bot_token = \"token\"").unwrap_err();
+        assert!(err.to_string().contains("chat_id or targets"), "{}", err);
+    }
 }
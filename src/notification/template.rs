@@ -0,0 +1,190 @@
+//! User defined messages, `{name}` placeholders are replaced with the variables of the event.
+//!
+//! Every event has `{event}` and `{message}`, the default text. `added` and `approval_requested` also have
+//! `{feed}`, `{title}`, `{size}`, `{files}`, `{info_hash}`, `{download_dir}` and `{link}`, the failures
+//! have `{error}` and `feed_failed` has `{feed}`. Unknown placeholders are kept as they are.
+
+use crate::config::ParseMode;
+use crate::notification::{Event, EventKind};
+use std::collections::HashMap;
+
+/// How a notifier turns events into text
+#[derive(Debug, Clone, Default)]
+pub struct Format {
+    pub templates: HashMap<EventKind, String>,
+    /// Markup the templates are written in, the variables are escaped for it
+    pub parse_mode: Option<ParseMode>,
+}
+
+impl Format {
+    /// The event rendered with its template, or its escaped default message
+    pub fn render(&self, event: &Event) -> String {
+        self.render_with(event, self.parse_mode)
+    }
+
+    /// Like [`Format::render`], escaping for `parse_mode` instead
+    pub fn render_with(&self, event: &Event, parse_mode: Option<ParseMode>) -> String {
+        match self.templates.get(&event.kind()) {
+            Some(template) => render(template, &event.variables(), parse_mode),
+            None => escape(&event.message(), parse_mode),
+        }
+    }
+}
+
+/// Replace the known `{name}` placeholders of `template`, escaping the values
+pub fn render(
+    template: &str,
+    variables: &[(&str, String)],
+    parse_mode: Option<ParseMode>,
+) -> String {
+    render_escaped(template, variables, |value| escape(value, parse_mode))
+}
+
+/// Like [`render`], escaping the values with `escape`, e.g. for a JSON body
+pub fn render_escaped(
+    template: &str,
+    variables: &[(&str, String)],
+    escape: impl Fn(&str) -> String,
+) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let variable = after.find('}').and_then(|end| {
+            variables
+                .iter()
+                .find(|(name, _)| *name == &after[..end])
+                .map(|(_, value)| (end, value))
+        });
+        match variable {
+            Some((end, value)) => {
+                out.push_str(&escape(value));
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Escape text so it shows up as is with the given markup
+pub fn escape(value: &str, parse_mode: Option<ParseMode>) -> String {
+    match parse_mode {
+        None => value.to_string(),
+        Some(ParseMode::MarkdownV2) => {
+            let mut out = String::with_capacity(value.len());
+            for c in value.chars() {
+                if "_*[]()~`>#+-=|{}.!\\".contains(c) {
+                    out.push('\\');
+                }
+                out.push(c);
+            }
+            out
+        }
+        Some(ParseMode::Html) => value
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;"),
+    }
+}
+
+/// Size in bytes with a binary unit, e.g. `1.50 GiB`
+pub fn human_size(bytes: i64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.2} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notification::AddedTorrent;
+
+    fn added() -> Event {
+        Event::Added {
+            feed: "anime".to_string(),
+            torrent: AddedTorrent {
+                title: "Show - 01 [1080p].mkv".to_string(),
+                size: Some(1536 * 1024 * 1024),
+                files: Some(1),
+                info_hash: "c12fe1c06bba254a9dc9f519b335aa7c1367a88a".to_string(),
+                download_dir: "/downloads/anime".to_string(),
+                link: "https://example.com/1.torrent".to_string(),
+                approval: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let template = "{title} ({size}, {files} files) from {feed} into {download_dir} {unknown}";
+        assert_eq!(
+            render(template, &added().variables(), None),
+            "Show - 01 [1080p].mkv (1.50 GiB, 1 files) from anime into /downloads/anime {unknown}"
+        );
+    }
+
+    #[test]
+    fn test_escape() {
+        let format = Format {
+            templates: HashMap::from([(EventKind::Added, "*{title}*".to_string())]),
+            parse_mode: Some(ParseMode::MarkdownV2),
+        };
+        assert_eq!(format.render(&added()), r"*Show \- 01 \[1080p\]\.mkv*");
+
+        let format = Format {
+            templates: HashMap::from([(
+                EventKind::Added,
+                "<a href=\"{link}\">{title}</a>".to_string(),
+            )]),
+            parse_mode: Some(ParseMode::Html),
+        };
+        let event = Event::Added {
+            feed: "anime".to_string(),
+            torrent: AddedTorrent {
+                title: "A & B <1080p>".to_string(),
+                link: "https://example.com/?a=1&b=2".to_string(),
+                ..Default::default()
+            },
+        };
+        assert_eq!(
+            format.render(&event),
+            "<a href=\"https://example.com/?a=1&amp;b=2\">A &amp; B &lt;1080p&gt;</a>"
+        );
+
+        // no template, the default message is escaped too
+        let event = Event::FeedFailed {
+            feed: "anime".to_string(),
+            error: "<timeout>".to_string(),
+        };
+        assert_eq!(
+            format.render(&event),
+            "Failed to process anime feed: &lt;timeout&gt;"
+        );
+    }
+
+    #[test]
+    fn test_human_size() {
+        assert_eq!(human_size(512), "512 B");
+        assert_eq!(human_size(1024), "1.00 KiB");
+        assert_eq!(
+            human_size(5 * 1024 * 1024 * 1024 * 1024 * 1024),
+            "5120.00 TiB"
+        );
+    }
+}
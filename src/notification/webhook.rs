@@ -0,0 +1,153 @@
+use crate::error::{Error, Result};
+use crate::notification::delivery::check_status;
+use crate::notification::template;
+use crate::notification::{Event, Notifier};
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use std::collections::HashMap;
+
+const BACKEND: &str = "webhook";
+
+/// POSTs events as JSON rendered from a template
+pub struct Webhook {
+    url: String,
+    body: String,
+    headers: HeaderMap,
+}
+
+impl Webhook {
+    /// Fails with a config error when a header isn't valid
+    pub fn new(url: String, body: String, headers: HashMap<String, String>) -> Result<Self> {
+        let invalid = |name: &str, err: &dyn std::fmt::Display| {
+            Error::Config(serde::de::Error::custom(format!(
+                "invalid webhook header {:?}: {}",
+                name, err
+            )))
+        };
+        let mut header_map = HeaderMap::new();
+        header_map.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        for (name, value) in headers.iter() {
+            let header =
+                HeaderName::from_bytes(name.as_bytes()).map_err(|err| invalid(name, &err))?;
+            let value = HeaderValue::from_str(value).map_err(|err| invalid(name, &err))?;
+            header_map.insert(header, value);
+        }
+        Ok(Self {
+            url,
+            body,
+            headers: header_map,
+        })
+    }
+
+    /// Body for `event`, the variables are JSON escaped so the template only has to quote them,
+    /// see [`template`] for the available ones
+    fn render(&self, event: &Event) -> String {
+        template::render_escaped(&self.body, &event.variables(), escape)
+    }
+
+    pub async fn send(&self, event: &Event) -> Result<()> {
+        let client = reqwest::Client::new();
+        let req = client
+            .post(&self.url)
+            .headers(self.headers.clone())
+            .body(self.render(event));
+        let res = req.send().await.map_err(|err| Error::Notification {
+            backend: BACKEND.to_string(),
+            source: err.into(),
+        })?;
+
+        check_status(BACKEND, res).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Notifier for Webhook {
+    fn name(&self) -> &str {
+        BACKEND
+    }
+
+    async fn notify(&self, event: &Event) -> Result<()> {
+        self.send(event).await
+    }
+}
+
+/// Content of a JSON string literal, without the quotes
+fn escape(value: &str) -> String {
+    let quoted = serde_json::to_string(value).expect("strings always serialize");
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notification::AddedTorrent;
+    use httpmock::prelude::*;
+    use serde_json::json;
+
+    fn event() -> Event {
+        Event::Added {
+            feed: "anime".to_string(),
+            torrent: AddedTorrent {
+                title: "\"quoted\" episode".to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_send() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/hook")
+                .header("Authorization", "Bearer secret")
+                .json_body(json!({
+                    "event": "added",
+                    "message": "Downloading: \"quoted\" episode",
+                    "title": "\"quoted\" episode",
+                    "feed": "anime",
+                }));
+            then.status(200);
+        });
+        let headers = HashMap::from([("Authorization".to_string(), "Bearer secret".to_string())]);
+        let notifier = Webhook::new(
+            server.url("/hook"),
+            r#"{"event": "{event}", "message": "{message}", "title": "{title}", "feed": "{feed}"}"#
+                .to_string(),
+            headers,
+        )
+        .unwrap();
+
+        let result = tokio_test::block_on(notifier.send(&event()));
+
+        mock.assert();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_send_rejected() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/hook");
+            then.status(400).body("invalid body");
+        });
+        let notifier = Webhook::new(server.url("/hook"), "{}".to_string(), HashMap::new()).unwrap();
+
+        let result = tokio_test::block_on(notifier.send(&event()));
+
+        assert!(matches!(result, Err(Error::NotificationRejected { .. })));
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let headers = HashMap::from([("Authorization".to_string(), "Bearer\nsecret".to_string())]);
+        let res = Webhook::new(
+            "http://localhost/hook".to_string(),
+            "{}".to_string(),
+            headers,
+        );
+        assert!(matches!(res, Err(Error::Config(_))));
+    }
+}
@@ -0,0 +1,177 @@
+//! Retries of the feed and .torrent requests and of the transmission RPC calls, with exponential
+//! backoff, jitter and the `Retry-After` of the server.
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Client, Response};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How failed requests are retried, configured globally and optionally per feed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Attempts of a request, the first one included
+    pub max_attempts: u32,
+    /// Seconds before the second attempt, doubled for every further one
+    pub base_delay: f64,
+    /// Upper bound in seconds of a single delay, `Retry-After` included
+    pub max_delay: f64,
+    /// Up to this fraction of the delay is added at random, so clients don't retry in lockstep
+    pub jitter: f64,
+    /// HTTP statuses worth another attempt, transport errors always are
+    pub statuses: Vec<u16>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            base_delay: 1.0,
+            max_delay: 60.0,
+            jitter: 0.2,
+            statuses: vec![408, 429, 500, 502, 503, 504],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retrying after `attempt` failed, `None` once every attempt is used.
+    ///
+    /// `retry_after` as asked by the server replaces the backoff.
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        let delay = match retry_after {
+            Some(val) => val,
+            None => {
+                let backoff = seconds(self.base_delay)
+                    .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+                let jitter = self.jitter.clamp(0.0, 1.0) * rand::random::<f64>();
+                backoff.saturating_add(backoff.mul_f64(jitter))
+            }
+        };
+        Some(delay.min(seconds(self.max_delay)))
+    }
+
+    /// GET `url`, retrying transport errors and the configured statuses.
+    ///
+    /// The last response is returned as is, callers still have to check its status.
+    pub async fn get(
+        &self,
+        client: &Client,
+        url: &str,
+        headers: &HeaderMap,
+    ) -> reqwest::Result<Response> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let res = client.get(url).headers(headers.clone()).send().await;
+            let retry_after = match &res {
+                Ok(val) if self.statuses.contains(&val.status().as_u16()) => {
+                    retry_after(val.headers())
+                }
+                Ok(_) => return res,
+                // a malformed request won't get any better
+                Err(err) if err.is_builder() => return res,
+                Err(_) => None,
+            };
+            match self.delay(attempt, retry_after) {
+                Some(delay) => {
+                    log::debug!("Retrying {} in {:?}", url, delay);
+                    tokio::time::sleep(delay).await;
+                }
+                None => return res,
+            }
+        }
+    }
+}
+
+/// `Retry-After` in seconds, the HTTP date form isn't supported
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)
+        .and_then(|it| it.to_str().ok())
+        .and_then(|it| it.trim().parse::<f64>().ok())
+        .map(seconds)
+}
+
+/// Duration of a configured number of seconds, negative values are zero
+fn seconds(value: f64) -> Duration {
+    Duration::try_from_secs_f64(value.max(0.0)).unwrap_or(Duration::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            base_delay: 0.01,
+            jitter: 0.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy {
+            jitter: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(policy.delay(1, None), Some(Duration::from_secs(1)));
+        assert_eq!(policy.delay(3, None), Some(Duration::from_secs(4)));
+        assert_eq!(policy.delay(4, None), None);
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_secs(7))),
+            Some(Duration::from_secs(7))
+        );
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_secs(3600))),
+            Some(Duration::from_secs(60))
+        );
+
+        let policy = RetryPolicy::default();
+        let delay = policy.delay(2, None).unwrap();
+        assert!(delay >= Duration::from_secs(2) && delay <= Duration::from_millis(2400));
+    }
+
+    #[tokio::test]
+    async fn test_get_retries_status() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/rss.xml");
+                then.status(503).header("Retry-After", "0");
+            })
+            .await;
+
+        let res = policy()
+            .get(&Client::new(), &server.url("/rss.xml"), &HeaderMap::new())
+            .await
+            .unwrap();
+
+        assert_eq!(res.status().as_u16(), 503);
+        assert_eq!(mock.hits_async().await, 4);
+    }
+
+    #[tokio::test]
+    async fn test_get_no_retry() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/rss.xml");
+                then.status(404);
+            })
+            .await;
+
+        let res = policy()
+            .get(&Client::new(), &server.url("/rss.xml"), &HeaderMap::new())
+            .await
+            .unwrap();
+
+        assert_eq!(res.status().as_u16(), 404);
+        assert_eq!(mock.hits_async().await, 1);
+    }
+}
@@ -1,192 +1,668 @@
-use crate::config::{Config, RssList};
-use crate::notification::notify_all;
+use crate::approval::Approval;
+use crate::config::{AddMode, Config, RssList};
+use crate::error::{Error, Result};
+use crate::failure::Failure;
+use crate::feed::{self, FeedItem};
+use crate::filter::Filter;
+use crate::http::Clients;
+use crate::limit::Limits;
+use crate::magnet;
+use crate::notification::{AddedTorrent, Event, Registry};
+use crate::retry::RetryPolicy;
+use futures::future::{BoxFuture, FutureExt};
 use lava_torrent::torrent::v1::Torrent;
 use openssl::base64;
-use rss::{Channel, Item};
+use reqwest::header::{
+    HeaderMap, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
 use sled::Db;
-use std::error::Error;
 use std::sync::Arc;
-use transmission_rpc::types::{BasicAuth, RpcResponse, TorrentAddArgs, TorrentAddedOrDuplicate};
+use transmission_rpc::types::{
+    BasicAuth, Result as RpcResult, RpcResponse, RpcResponseArgument, TorrentAddArgs,
+    TorrentAddedOrDuplicate,
+};
 use transmission_rpc::TransClient;
+/// sled tree of already processed feed items, keyed by [`item_key`]
+const ITEMS_TREE: &str = "items";
+/// sled tree of the [`Validators`] of the feeds, keyed by feed url
+pub(crate) const FEEDS_TREE: &str = "feeds";
+
+enum TorrentSource {
+    File {
+        torrent: Box<Torrent>,
+        /// Raw content of the .torrent file
+        metainfo: Vec<u8>,
+        url: String,
+    },
+    Magnet(String),
+}
+
 struct TorrentItem {
     pub title: String,
-    pub torrent: Torrent,
+    pub source: TorrentSource,
+    /// Lowercase hex info-hash, used for dedup against transmission
+    pub info_hash: String,
+    /// Key of the feed item in [`ITEMS_TREE`]
+    pub key: Option<String>,
+    /// Token of the approval record, for items of approval mode feeds
+    pub approval: Option<String>,
+    /// Page of the feed item, its `link`, as opposed to the torrent or magnet link
+    pub page: Option<String>,
 }
 impl TorrentItem {
     pub async fn new(
+        client: &reqwest::Client,
+        retry: &RetryPolicy,
         url: String,
         title: String,
-    ) -> Result<TorrentItem, Box<dyn Error + Send + Sync>> {
-        let res = get_with_retry(&url, 1).await;
-        if res.is_err() {
-            return Err(format!("Failed to fetch the torrent file : {:?}", res).into());
+    ) -> Result<TorrentItem> {
+        // magnet links already carry the info-hash, no need to fetch anything
+        if magnet::is_magnet(&url) {
+            let info_hash = match magnet::info_hash(&url) {
+                Some(val) => val,
+                None => return Err(Error::InvalidMagnet { url }),
+            };
+            return Ok(TorrentItem {
+                title,
+                source: TorrentSource::Magnet(url),
+                info_hash,
+                key: None,
+                approval: None,
+                page: None,
+            });
         }
-        let res = res.unwrap();
-        if res.status() != 200 {
-            // return Err(fmt"Failed to fetch the torrent file : {:?}".into());
-            return Err(format!(
-                "Failed to fetch the torrent file :{:?}; url = {:?}",
-                res, url
-            )
-            .into());
+
+        let fetch = async {
+            retry
+                .get(client, &url, &HeaderMap::new())
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await
+        };
+        let metainfo = match fetch.await {
+            Ok(val) => val.to_vec(),
+            Err(source) => return Err(Error::TorrentFetch { url, source }),
+        };
+        let torrent = match Torrent::read_from_bytes(&metainfo) {
+            Ok(val) => val,
+            Err(source) => return Err(Error::InvalidTorrent { url, source }),
+        };
+        Ok(TorrentItem {
+            title,
+            info_hash: torrent.info_hash(),
+            source: TorrentSource::File {
+                torrent: Box::new(torrent),
+                metainfo,
+                url,
+            },
+            key: None,
+            approval: None,
+            page: None,
+        })
+    }
+
+    pub fn magnet_link(&self) -> Result<String> {
+        match &self.source {
+            TorrentSource::File { torrent, url, .. } => {
+                torrent
+                    .magnet_link()
+                    .map_err(|source| Error::InvalidTorrent {
+                        url: url.clone(),
+                        source,
+                    })
+            }
+            TorrentSource::Magnet(link) => Ok(link.clone()),
+        }
+    }
+
+    /// Link the torrent was fetched from, or the magnet link
+    pub fn download_link(&self) -> &str {
+        match &self.source {
+            TorrentSource::File { url, .. } => url,
+            TorrentSource::Magnet(link) => link,
+        }
+    }
+
+    /// Details of the torrent for notifications, linking to the page of the feed item if known
+    pub fn added(&self, download_dir: &str) -> AddedTorrent {
+        let (size, files) = match &self.source {
+            TorrentSource::File { torrent, .. } => (
+                Some(torrent.length),
+                Some(torrent.files.as_ref().map_or(1, Vec::len)),
+            ),
+            TorrentSource::Magnet(_) => (None, None),
+        };
+        let link = self.page.as_deref().unwrap_or(self.download_link());
+        AddedTorrent {
+            title: self.title.clone(),
+            size,
+            files,
+            info_hash: self.info_hash.clone(),
+            download_dir: download_dir.to_string(),
+            link: link.to_string(),
+            approval: self.approval.clone(),
         }
-        // let mut buffer: Vec<u8> = Vec::new();
-        // res.into_reader().read_to_end(&mut buffer).unwrap();
+    }
 
-        // let torrent = Torrent::read_from_bytes(&buffer)?;
-        let torrent = Torrent::read_from_bytes(&res.bytes().await?)?;
-        Ok(TorrentItem { title, torrent })
+    /// Build the `torrent-add` arguments for the given mode
+    pub fn add_args(&self, mode: AddMode, download_dir: String) -> Result<TorrentAddArgs> {
+        let (filename, metainfo) = match (&self.source, mode) {
+            (TorrentSource::Magnet(link), _) => (Some(link.clone()), None),
+            (TorrentSource::File { url, .. }, AddMode::Url) => (Some(url.clone()), None),
+            (TorrentSource::File { .. }, AddMode::Magnet) => (Some(self.magnet_link()?), None),
+            (TorrentSource::File { metainfo, .. }, AddMode::Metainfo) => {
+                (None, Some(base64::encode_block(metainfo)))
+            }
+        };
+        Ok(TorrentAddArgs {
+            filename,
+            metainfo,
+            // transmission's default directory for manual adds
+            download_dir: Some(download_dir).filter(|it| !it.is_empty()),
+            ..TorrentAddArgs::default()
+        })
     }
 }
 
-async fn get_with_retry(
-    url: &str,
-    retry: u32,
-) -> Result<reqwest::Response, Box<dyn Error + Send + Sync>> {
-    let mut count = 0;
-    loop {
-        let res = reqwest::get(url).await;
-        if res.is_ok() {
-            return Ok(res?);
+/// `ETag` and `Last-Modified` of the last body of a feed, sent back to only get a changed feed
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+struct Validators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// [`filters_hash`] of the feed the body was scanned with
+    #[serde(default)]
+    filters: String,
+}
+
+impl Validators {
+    /// Validators of the last body, none once the filters changed so the new ones get applied
+    fn load(db: &Db, item: &RssList) -> Result<Validators> {
+        let value = db.open_tree(FEEDS_TREE)?.get(&item.url)?;
+        Ok(value
+            .and_then(|it| serde_json::from_slice::<Validators>(&it).ok())
+            .filter(|it| it.filters == filters_hash(item))
+            .unwrap_or_default())
+    }
+
+    fn save(&self, db: &Db, feed_url: &str) -> Result<()> {
+        let value = serde_json::to_vec(self).expect("validators always serialize");
+        db.open_tree(FEEDS_TREE)?.insert(feed_url, value)?;
+        Ok(())
+    }
+
+    fn from_response(headers: &HeaderMap, item: &RssList) -> Validators {
+        let get = |name| {
+            headers
+                .get(name)
+                .and_then(|it: &HeaderValue| it.to_str().ok())
+                .map(str::to_string)
+        };
+        Validators {
+            etag: get(ETAG),
+            last_modified: get(LAST_MODIFIED),
+            filters: filters_hash(item),
         }
-        count += 1;
-        if count > retry {
-            return Err(format!("Failed to fetch the torrent file : {:?}", url).into());
+    }
+
+    /// `If-None-Match`/`If-Modified-Since` request headers
+    fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let values = [
+            (IF_NONE_MATCH, &self.etag),
+            (IF_MODIFIED_SINCE, &self.last_modified),
+        ];
+        for (name, value) in values {
+            if let Some(val) = value
+                .as_deref()
+                .and_then(|it| HeaderValue::from_str(it).ok())
+            {
+                headers.insert(name, val);
+            }
         }
-        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        headers
     }
 }
 
+/// Hex SHA-256 of the filters of a feed
+fn filters_hash(item: &RssList) -> String {
+    let value = serde_json::to_vec(&item.filters).expect("filters always serialize");
+    openssl::sha::sha256(&value)
+        .iter()
+        .map(|it| format!("{:02x}", it))
+        .collect()
+}
+
+/// What a feed is scanned for
+enum ScanMode<'a> {
+    /// Fetch only a changed feed, keeping the body in `cache_dir` when given
+    Run { cache_dir: Option<&'a str> },
+    /// Always fetch the full feed, so the current filters can be tried out
+    DryRun,
+}
+
+/// Feed items left after the filters and the dedup checks
+#[derive(Default)]
+struct FeedScan {
+    /// The feed didn't change since the last run, nothing was scanned
+    not_modified: bool,
+    new: Vec<TorrentItem>,
+    /// Items whose torrent is already in the db, as (item key, info-hash)
+    known: Vec<(String, String)>,
+    /// Items whose torrent couldn't be fetched, with their updated failure record
+    failed: Vec<Failure>,
+    /// Items that failed before and wait for their retry delay
+    waiting: usize,
+    /// Validators of the fetched body, `None` when the feed wasn't modified
+    validators: Option<Validators>,
+}
+
+enum ScannedItem {
+    New(TorrentItem),
+    Known { key: String, info_hash: String },
+    Failed(Failure),
+    Waiting,
+}
+
 pub async fn process_feed(
     db: Arc<Db>,
     item: RssList,
     cfg: Config,
-) -> Result<i32, Box<dyn Error + Send + Sync>> {
+    clients: &Clients,
+    limits: Arc<Limits>,
+    notifiers: Arc<Registry>,
+) -> Result<i32> {
+    let _permit = limits.feed().await;
     println!("----------------------------");
     println!("==> Processing [{}]", item.title);
 
-    // Fetch the url
-    let content = get_with_retry(&item.url, 3).await?.bytes().await?;
+    let scan = scan_feed(
+        &db,
+        &item,
+        &cfg,
+        clients,
+        &limits,
+        ScanMode::Run {
+            cache_dir: cfg.persistence.feed_cache.as_deref(),
+        },
+    )
+    .await?;
+    if scan.not_modified {
+        return Ok(0);
+    }
+    for failure in scan.failed.iter() {
+        if failure.quarantined {
+            log::warn!(
+                "Quarantined {} after {} failed attempts: {}",
+                failure.title,
+                failure.attempts,
+                failure.last_error
+            );
+        }
+        failure.save(&db)?;
+    }
+    // fetched fine this time
+    let fetched = scan.new.iter().filter_map(|it| it.key.as_ref());
+    for key in fetched.chain(scan.known.iter().map(|(key, _)| key)) {
+        Failure::delete(&db, key)?;
+    }
+    // torrents added by other means, remember the items for next time
+    let seen = db.open_tree(ITEMS_TREE)?;
+    for (key, info_hash) in scan.known {
+        seen.insert(key, info_hash.as_bytes())?;
+    }
+    let url = item.url.clone();
+    let count = if item.approval {
+        request_approval(db.clone(), item, &notifiers, scan.new).await?
+    } else {
+        download_torrents(db.clone(), item, cfg, &notifiers, scan.new).await?
+    };
+    // only once every item made it, a 304 would skip the failed ones
+    let pending = scan.failed.len() + scan.waiting;
+    if let Some(validators) = scan.validators.filter(|_| pending == 0) {
+        validators.save(&db, &url)?;
+    }
+    Ok(count)
+}
+
+/// Store the new items of an approval mode feed and ask for approval, nothing is added yet
+async fn request_approval(
+    db: Arc<Db>,
+    item: RssList,
+    notifiers: &Registry,
+    results: Vec<TorrentItem>,
+) -> Result<i32> {
+    log::info!(
+        "[{:?}] [{:?}] torrents waiting for approval",
+        item.title,
+        results.len()
+    );
+    let seen = db.open_tree(ITEMS_TREE)?;
+    for result in results {
+        // the same torrent may show up again under another item
+        if Approval::get(&db, &result.info_hash)?.is_none() {
+            let mut torrent = result.added(&item.download_dir);
+            let approval = Approval {
+                feed: item.title.clone(),
+                title: result.title.clone(),
+                link: result.download_link().to_string(),
+                page: result.page.clone(),
+                key: result.key.clone(),
+                info_hash: result.info_hash.clone(),
+                token: Approval::new_token(),
+                added: false,
+            };
+            approval.save(&db)?;
+            torrent.approval = Some(approval.token);
+            let event = Event::ApprovalRequested {
+                feed: item.title.clone(),
+                torrent,
+            };
+            notifiers.notify_all(&event).await;
+        }
+        if let Some(key) = &result.key {
+            seen.insert(key, result.info_hash.as_bytes())?;
+        }
+    }
+    db.flush()?;
+    Ok(0)
+}
+
+/// Add an approved item, with the settings of its feed if it's still configured
+pub async fn add_approved(
+    db: Arc<Db>,
+    approval: &Approval,
+    feed: Option<RssList>,
+    cfg: Config,
+    clients: &Clients,
+    notifiers: &Registry,
+) -> Result<i32> {
+    let feed = feed.unwrap_or_else(manual_feed);
+    let client = clients.get(&feed.http)?;
+    let retry = cfg.retry_for(&feed);
+    let mut item = TorrentItem::new(
+        &client,
+        retry,
+        approval.link.clone(),
+        approval.title.clone(),
+    )
+    .await?;
+    item.key = approval.key.clone();
+    item.approval = Some(approval.token.clone());
+    item.page = approval.page.clone();
+    download_torrents(db, feed, cfg, notifiers, vec![item]).await
+}
+
+/// Settings of torrents added without a feed, transmission's defaults
+fn manual_feed() -> RssList {
+    RssList {
+        title: "manual".to_string(),
+        url: String::new(),
+        filters: Default::default(),
+        download_dir: String::new(),
+        add_mode: Default::default(),
+        interval: None,
+        approval: false,
+        retry: None,
+        http: Default::default(),
+    }
+}
+
+/// Add a single torrent or magnet link, with the settings of `feed` if given.
+///
+/// Returns the number of torrents added, 0 when transmission already has it.
+pub async fn add_link(
+    db: Arc<Db>,
+    link: String,
+    feed: Option<RssList>,
+    cfg: Config,
+    clients: &Clients,
+    notifiers: &Registry,
+) -> Result<i32> {
+    let feed = feed.unwrap_or_else(manual_feed);
+    let client = clients.get(&feed.http)?;
+    let mut item = TorrentItem::new(&client, cfg.retry_for(&feed), link.clone(), link).await?;
+    if let TorrentSource::File { torrent, .. } = &item.source {
+        item.title = torrent.name.clone();
+    }
+    download_torrents(db, feed, cfg, notifiers, vec![item]).await
+}
+
+/// Titles of the feed items that would be added, without writing to the db or contacting transmission
+pub async fn dry_run_feed(
+    db: Arc<Db>,
+    item: RssList,
+    cfg: Config,
+    clients: &Clients,
+    limits: Arc<Limits>,
+) -> Result<Vec<String>> {
+    let _permit = limits.feed().await;
+    let scan = scan_feed(&db, &item, &cfg, clients, &limits, ScanMode::DryRun).await?;
+    Ok(scan.new.into_iter().map(|it| it.title).collect())
+}
+
+/// Fetch the feed and check every item against the filters and the db, read only.
+///
+/// The body is also written to the cache dir of a run when given.
+async fn scan_feed(
+    db: &Arc<Db>,
+    item: &RssList,
+    cfg: &Config,
+    clients: &Clients,
+    limits: &Limits,
+    mode: ScanMode<'_>,
+) -> Result<FeedScan> {
+    let client = clients.get(&item.http)?;
+    let retry = cfg.retry_for(item);
+    let quarantine = &cfg.quarantine;
+    // Fetch the url, unless it's the same as last time
+    let (headers, cache_dir) = match mode {
+        ScanMode::Run { cache_dir } => (Validators::load(db, item)?.headers(), cache_dir),
+        ScanMode::DryRun => (HeaderMap::new(), None),
+    };
+    let fetch = async {
+        let _permit = limits.feed_fetch(&item.url).await;
+        let res = retry.get(&client, &item.url, &headers).await?;
+        if res.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+        let res = res.error_for_status()?;
+        let validators = Validators::from_response(res.headers(), item);
+        Ok(Some((res.bytes().await?, validators)))
+    };
+    let (content, validators) = match fetch.await {
+        Ok(Some(val)) => val,
+        Ok(None) => {
+            log::info!("[{:?}] feed not modified", item.title);
+            return Ok(FeedScan {
+                not_modified: true,
+                ..Default::default()
+            });
+        }
+        Err(source) => {
+            return Err(Error::FeedFetch {
+                url: item.url.clone(),
+                source,
+            })
+        }
+    };
     log::info!("[{:?}] feed fetched", item.title);
-    let channel = Channel::read_from(&content[..])?;
+    if let Some(dir) = cache_dir {
+        cache_body(dir, &item.title, &content).await;
+    }
+    let items = match feed::parse(&content) {
+        Ok(val) => val,
+        Err(source) => {
+            return Err(Error::FeedParse {
+                url: item.url.clone(),
+                source,
+            })
+        }
+    };
 
-    let tasks = channel
-        .items
+    let filter = Filter::new(&item.filters)?;
+    let seen = db.open_tree(ITEMS_TREE)?;
+    let tasks = items
         .into_iter()
         .map(|it| {
             let db_copy = db.clone();
-            let filters = item.filters.clone();
+            let filter = &filter;
+            let seen = &seen;
+            let feed_url = &item.url;
+            let client = &client;
             async move {
-                // TODO vaoid some fetch in new, add some cache or db check
-                let it = TorrentItem::new(
-                    get_link(&it).to_string(),
-                    it.title().unwrap_or_default().to_string(),
-                )
-                .await;
-                if let Err(err) = it {
-                    log::warn!("Failed to process item: {}", err);
+                // check filter before fetching anything, it only needs the title
+                let title = it.title.as_str();
+                if !filter.matches(title) {
+                    log::debug!("Skipping {} as it doesn't match the filters", title);
                     return None;
                 }
-                let it = it.unwrap();
 
-                // check if item is already on db
-                let db_found = match db_copy.get(it.torrent.clone().info_hash()) {
-                    Ok(val) => val,
-                    Err(_) => None,
-                };
-                if db_found.is_some() {
+                // skip items processed by a previous run without any request
+                let key = item_key(feed_url, &it);
+                if seen.contains_key(&key).unwrap_or_default() {
+                    log::debug!("Skipping {} as it was already processed", title);
                     return None;
                 }
 
-                // check filter, if no filter, default to true
-                let mut found = true;
-                if !filters.is_empty() {
-                    found = false;
-                    for filter in filters {
-                        if it.title.contains(&filter) {
-                            found = true;
-                        }
+                // items that failed before wait for their delay, quarantined ones for the user
+                let failure = Failure::get(&db_copy, &key).unwrap_or_default();
+                match &failure {
+                    Some(val) if val.quarantined => {
+                        log::debug!("Skipping {} as it's quarantined", title);
+                        return None;
                     }
-
-                    if !found {
-                        log::debug!("Skipping {} as it doesn't match any filter", it.title)
+                    Some(val) if !val.is_due(quarantine) => {
+                        log::debug!("Skipping {} until its retry delay is over", title);
+                        return Some(ScannedItem::Waiting);
                     }
+                    _ => {}
                 }
-                if found {
-                    Some(it)
-                } else {
+
+                let link = get_link(&it);
+                let page = it.link.clone();
+                // magnet links are handled without any request
+                let permit = if magnet::is_magnet(link) {
                     None
+                } else {
+                    Some(limits.torrent_fetch(link).await)
+                };
+                let it = TorrentItem::new(client, retry, link.to_string(), title.to_string()).await;
+                drop(permit);
+                if let Err(err) = it {
+                    log::warn!("Failed to process item: {}", err);
+                    let mut failure = failure.unwrap_or_else(|| {
+                        Failure::new(key, item, title.to_string(), link.to_string())
+                    });
+                    failure.record(quarantine, err.to_string());
+                    return Some(ScannedItem::Failed(failure));
                 }
+                let mut it = it.unwrap();
+                it.page = page;
+
+                // check if item is already on db
+                let db_found = db_copy.get(&it.info_hash).unwrap_or_default();
+                if db_found.is_some() {
+                    return Some(ScannedItem::Known {
+                        key,
+                        info_hash: it.info_hash,
+                    });
+                }
+                it.key = Some(key);
+                Some(ScannedItem::New(it))
             }
         })
         .collect::<Vec<_>>();
 
-    // let results: Vec<Option<TorrentItem>> = futures::future::join_all(tasks).await;
-    let mut results = Vec::new();
-    for task in tasks {
-        let res = task.await;
-        if res.is_some() {
-            results.push(res);
+    let mut scan = FeedScan {
+        validators: Some(validators),
+        ..Default::default()
+    };
+    for task in futures::future::join_all(tasks).await {
+        match task {
+            Some(ScannedItem::New(it)) => scan.new.push(it),
+            Some(ScannedItem::Known { key, info_hash }) => scan.known.push((key, info_hash)),
+            Some(ScannedItem::Failed(failure)) => scan.failed.push(failure),
+            Some(ScannedItem::Waiting) => scan.waiting += 1,
+            None => {}
         }
     }
-    download_torrents(db, item, cfg, results).await
+    Ok(scan)
+}
+
+/// Keep the last body of a feed for debugging, named after the feed title
+async fn cache_body(dir: &str, title: &str, content: &[u8]) {
+    let name: String = title
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    let path = std::path::Path::new(dir).join(name);
+    let res = async {
+        tokio::fs::create_dir_all(dir).await?;
+        tokio::fs::write(&path, content).await
+    };
+    if let Err(err) = res.await {
+        log::warn!("Failed to cache the feed in {:?}: {}", path, err);
+    }
 }
 
 async fn download_torrents(
     db: Arc<Db>,
     item: RssList,
     cfg: Config,
-    results: Vec<Option<TorrentItem>>,
-) -> Result<i32, Box<dyn Error + Send + Sync>> {
+    notifiers: &Registry,
+    results: Vec<TorrentItem>,
+) -> Result<i32> {
     log::info!("[{:?}] [{:?}] torrents found", item.title, results.len());
 
     // Creates a new connection
-    let mut client = get_client(&cfg);
+    let mut client = get_client(&cfg)?;
+    let retry = cfg.retry_for(&item);
+    let seen = db.open_tree(ITEMS_TREE)?;
 
     let mut count = 0;
-    for result in results.iter().filter(|it| it.is_some()) {
-        let result = result.as_ref().unwrap();
+    for result in results.iter() {
         log::info!("Adding torrent: {}", result.title);
         // Add the torrent into transmission
-        let add: TorrentAddArgs = TorrentAddArgs {
-            filename: Some(result.torrent.magnet_link().unwrap()),
-            download_dir: Some(item.download_dir.clone()),
-            ..TorrentAddArgs::default()
-        };
-        let res = {
-            let retry = 3;
-            let mut count = 0;
-            loop {
-                let res: RpcResponse<TorrentAddedOrDuplicate> =
-                    client.torrent_add(add.clone()).await?;
-                if res.is_ok() {
-                    break res;
-                }
-                count += 1;
-                if count > retry {
-                    return Err(format!("Failed to add torrent: {}", result.title).into());
-                }
-                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-            }
-        };
-        if !res.is_ok() {
-            log::warn!("Failed to add torrent: {}", result.title);
-            continue;
-        }
+        let add: TorrentAddArgs = result.add_args(item.add_mode, item.download_dir.clone())?;
+        let added = rpc_call(
+            &cfg,
+            retry,
+            &mut client,
+            |client| client.torrent_add(add.clone()).boxed(),
+            |res| Error::TorrentAdd {
+                title: result.title.clone(),
+                result: res,
+            },
+        )
+        .await?;
 
         // check if torrent was added
-        match res.arguments {
+        match added {
             TorrentAddedOrDuplicate::TorrentAdded(torrent) => {
                 count += 1;
                 // send notification
-                notify_all(cfg.clone(), format!("Downloading: {}", result.title)).await;
+                let event = Event::Added {
+                    feed: item.title.clone(),
+                    torrent: result.added(&item.download_dir),
+                };
+                notifiers.notify_all(&event).await;
                 // Save the hash on the database
-                db.insert(torrent.hash_string.unwrap(), b"").unwrap();
+                let hash = torrent.hash_string.unwrap();
+                db.insert(&hash, b"")?;
+                if let Some(key) = &result.key {
+                    seen.insert(key, hash.as_bytes())?;
+                }
             }
             TorrentAddedOrDuplicate::TorrentDuplicate(torrent) => {
                 let hash = torrent.hash_string.unwrap();
                 log::warn!("Torrent already exists: {}", hash);
-                db.insert(hash, b"").unwrap();
+                db.insert(&hash, b"")?;
+                if let Some(key) = &result.key {
+                    seen.insert(key, hash.as_bytes())?;
+                }
             }
         }
     }
@@ -196,31 +672,108 @@ async fn download_torrents(
     Ok(count)
 }
 
-fn get_link(item: &Item) -> &str {
-    match item.enclosure() {
-        Some(enclosure) if enclosure.mime_type() == "application/x-bittorrent" => enclosure.url(),
-        _ => item.link().unwrap_or_default(),
+fn get_link(item: &FeedItem) -> &str {
+    match &item.enclosure {
+        Some(enclosure)
+            if enclosure.mime_type == feed::BITTORRENT || magnet::is_magnet(&enclosure.url) =>
+        {
+            &enclosure.url
+        }
+        _ => item.link.as_deref().unwrap_or_default(),
     }
 }
 
-pub fn get_client(cfg: &Config) -> TransClient {
+/// Identify a feed item by its guid, falling back to its link, scoped to the feed url
+/// so opaque guids of different sites can't collide
+fn item_key(feed_url: &str, item: &FeedItem) -> String {
+    let id = match &item.guid {
+        Some(guid) => guid,
+        None => get_link(item),
+    };
+    format!("{} {}", feed_url, id)
+}
+
+pub fn get_client(cfg: &Config) -> Result<TransClient> {
     let basic_auth = BasicAuth {
         user: cfg.transmission.username.clone(),
         password: cfg.transmission.password.clone(),
     };
-    TransClient::with_auth(cfg.transmission.url.parse().unwrap(), basic_auth)
+    let url = match cfg.transmission.url.parse() {
+        Ok(val) => val,
+        Err(source) => {
+            return Err(Error::InvalidUrl {
+                url: cfg.transmission.url.clone(),
+                source,
+            })
+        }
+    };
+    Ok(TransClient::with_auth(url, basic_auth))
+}
+
+/// Call transmission until it succeeds or `retry` gives up, returning the arguments of the response.
+///
+/// Rejected credentials aren't retried, an unsuccessful result is turned into an error by `failed`.
+pub async fn rpc_call<T, F>(
+    cfg: &Config,
+    retry: &RetryPolicy,
+    client: &mut TransClient,
+    call: F,
+    failed: impl Fn(String) -> Error,
+) -> Result<T>
+where
+    T: RpcResponseArgument,
+    F: for<'a> Fn(&'a mut TransClient) -> BoxFuture<'a, RpcResult<RpcResponse<T>>>,
+{
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let err = match call(client).await {
+            Ok(val) if val.is_ok() => return Ok(val.arguments),
+            Ok(val) => failed(val.result),
+            // rejected credentials won't get any better
+            Err(err) => match rpc_error(cfg, err) {
+                err @ Error::TransmissionAuth { .. } => return Err(err),
+                err => err,
+            },
+        };
+        match retry.delay(attempt, None) {
+            Some(delay) => {
+                log::warn!("{}, retrying in {:?}", err, delay);
+                tokio::time::sleep(delay).await;
+            }
+            None => return Err(err),
+        }
+    }
+}
+
+/// Classify a failed RPC call from its error.
+///
+/// Transmission answers every authorized request with JSON, only the error pages of rejected
+/// credentials or addresses fail to decode, transmission-rpc doesn't keep their status.
+pub fn rpc_error(cfg: &Config, err: Box<dyn std::error::Error + Send + Sync>) -> Error {
+    match err.downcast_ref::<reqwest::Error>() {
+        Some(val) if val.is_decode() => Error::TransmissionAuth {
+            url: cfg.transmission.url.clone(),
+        },
+        _ => Error::Transmission(err),
+    }
 }
 
 /**Get base64 of content of .torrent file url, incase some url can't be processed bt transmission */
 #[allow(dead_code)]
-async fn get_metainfo(url: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
-    let res = reqwest::get(url).await?;
+async fn get_metainfo(url: &str) -> Result<String> {
+    let fetch = async { reqwest::get(url).await?.error_for_status()?.bytes().await };
+    let content = match fetch.await {
+        Ok(val) => val,
+        Err(source) => {
+            return Err(Error::TorrentFetch {
+                url: url.to_string(),
+                source,
+            })
+        }
+    };
     // base 64
-    if res.error_for_status_ref().is_err() {
-        // return Err(fmt"Failed to fetch the torrent file : {:?}".into());
-        return Err(format!("Failed to fetch the torrent file : {:?}", url).into());
-    }
-    let metainfo = base64::encode_block(res.bytes().await?.as_ref());
+    let metainfo = base64::encode_block(content.as_ref());
     Ok(metainfo)
 }
 
@@ -228,6 +781,707 @@ async fn get_metainfo(url: &str) -> Result<String, Box<dyn Error + Send + Sync>>
 mod test {
 
     use super::*;
+    use httpmock::prelude::*;
+
+    /// Minimal single file torrent
+    const TEST_TORRENT: &[u8] = b"d4:infod6:lengthi1e4:name4:test12:piece lengthi16384e6:pieces20:\
+        \xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xffee";
+
+    fn test_config(server_url: &str) -> Config {
+        toml::from_str(&format!(
+            r#"
+            [persistence]
+            path = "./test"
+            [transmission]
+            url = "{0}/transmission/rpc"
+            username = "user"
+            password = "pass"
+            [notification]
+            [retry]
+            base_delay = 0.01
+            [[rss_list]]
+            title = "test"
+            url = "{0}/rss.xml"
+            download_dir = "/downloads"
+            "#,
+            server_url
+        ))
+        .unwrap()
+    }
+
+    fn test_feed(server_url: &str) -> String {
+        format!(
+            r#"<rss version="2.0"><channel><title>test</title><link>{0}</link><description/>
+            <item><title>Show - 01</title><guid>item-1</guid><link>{0}/1.torrent</link></item>
+            </channel></rss>"#,
+            server_url
+        )
+    }
+
+    #[tokio::test]
+    async fn test_seen_item_skips_fetch() {
+        let server = MockServer::start_async().await;
+        let feed_mock = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/rss.xml");
+                then.status(200).body(test_feed(&server.url("")));
+            })
+            .await;
+        let torrent_mock = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/1.torrent");
+                then.status(404);
+            })
+            .await;
+
+        let cfg = test_config(&server.url(""));
+        let feed = cfg.rss_list[0].clone();
+        let db = Arc::new(sled::Config::new().temporary(true).open().unwrap());
+        db.open_tree(ITEMS_TREE)
+            .unwrap()
+            .insert(format!("{} item-1", feed.url), b"")
+            .unwrap();
+
+        let count = process_feed(
+            db,
+            feed,
+            cfg.clone(),
+            &Default::default(),
+            Default::default(),
+            Default::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(count, 0);
+        feed_mock.assert_async().await;
+        assert_eq!(torrent_mock.hits_async().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_failed_item() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/rss.xml");
+                then.status(200).body(test_feed(&server.url("")));
+            })
+            .await;
+        let torrent_mock = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/1.torrent");
+                then.status(404);
+            })
+            .await;
+
+        let mut cfg = test_config(&server.url(""));
+        cfg.quarantine.max_failures = 2;
+        let feed = cfg.rss_list[0].clone();
+        let key = format!("{} item-1", feed.url);
+        let db = Arc::new(sled::Config::new().temporary(true).open().unwrap());
+        let clients = Clients::default();
+        let run = || {
+            process_feed(
+                db.clone(),
+                feed.clone(),
+                cfg.clone(),
+                &clients,
+                Default::default(),
+                Default::default(),
+            )
+        };
+
+        assert_eq!(run().await.unwrap(), 0);
+        let failure = Failure::get(&db, &key).unwrap().unwrap();
+        assert_eq!(failure.attempts, 1);
+        assert!(!failure.quarantined);
+        assert_eq!(torrent_mock.hits_async().await, 1);
+
+        // still waiting for its delay
+        assert_eq!(run().await.unwrap(), 0);
+        assert_eq!(torrent_mock.hits_async().await, 1);
+        assert!(db.open_tree(FEEDS_TREE).unwrap().is_empty());
+
+        let mut failure = failure;
+        failure.last_attempt = 0;
+        failure.save(&db).unwrap();
+        assert_eq!(run().await.unwrap(), 0);
+        assert_eq!(torrent_mock.hits_async().await, 2);
+        assert!(Failure::get(&db, &key).unwrap().unwrap().quarantined);
+
+        // quarantined items are left alone
+        assert_eq!(run().await.unwrap(), 0);
+        assert_eq!(torrent_mock.hits_async().await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_add_metainfo() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/rss.xml");
+                then.status(200).body(test_feed(&server.url("")));
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/1.torrent");
+                then.status(200).body(TEST_TORRENT);
+            })
+            .await;
+        let info_hash = Torrent::read_from_bytes(TEST_TORRENT).unwrap().info_hash();
+        let rpc_mock = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/transmission/rpc")
+                    .body_contains(format!(
+                        "\"metainfo\":\"{}\"",
+                        base64::encode_block(TEST_TORRENT)
+                    ));
+                then.status(200).json_body(serde_json::json!({
+                    "arguments": {
+                        "torrent-added": { "hashString": info_hash, "id": 1, "name": "test" }
+                    },
+                    "result": "success"
+                }));
+            })
+            .await;
+
+        let cfg = test_config(&server.url(""));
+        let feed = cfg.rss_list[0].clone();
+        let db = Arc::new(sled::Config::new().temporary(true).open().unwrap());
+
+        let count = process_feed(
+            db.clone(),
+            feed.clone(),
+            cfg.clone(),
+            &Default::default(),
+            Default::default(),
+            Default::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(count, 1);
+        rpc_mock.assert_async().await;
+        assert!(db.contains_key(&info_hash).unwrap());
+        let seen = db.open_tree(ITEMS_TREE).unwrap();
+        assert!(seen.contains_key(format!("{} item-1", feed.url)).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_approval_flow() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/rss.xml");
+                then.status(200).body(test_feed(&server.url("")));
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/1.torrent");
+                then.status(200).body(TEST_TORRENT);
+            })
+            .await;
+        let info_hash = Torrent::read_from_bytes(TEST_TORRENT).unwrap().info_hash();
+        let add_mock = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/transmission/rpc")
+                    .body_contains("torrent-add");
+                then.status(200).json_body(serde_json::json!({
+                    "arguments": {
+                        "torrent-added": { "hashString": info_hash, "id": 1, "name": "test" }
+                    },
+                    "result": "success"
+                }));
+            })
+            .await;
+        let remove_mock = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/transmission/rpc")
+                    .body_contains("torrent-remove")
+                    .body_contains(&info_hash);
+                then.status(200)
+                    .json_body(serde_json::json!({ "arguments": {}, "result": "success" }));
+            })
+            .await;
+
+        let mut cfg = test_config(&server.url(""));
+        cfg.rss_list[0].approval = true;
+        let feed = cfg.rss_list[0].clone();
+        let db = Arc::new(sled::Config::new().temporary(true).open().unwrap());
+        let notifiers = Arc::new(Registry::new());
+
+        let count = process_feed(
+            db.clone(),
+            feed.clone(),
+            cfg.clone(),
+            &Default::default(),
+            Default::default(),
+            notifiers.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(count, 0);
+        assert_eq!(add_mock.hits_async().await, 0);
+        let approval = Approval::get(&db, &info_hash).unwrap().unwrap();
+        assert_eq!(approval.title, "Show - 01");
+        assert!(!approval.added);
+
+        let answer = crate::approval::handle(
+            &db,
+            &cfg,
+            &Default::default(),
+            &notifiers,
+            crate::approval::Action::Approve,
+            &info_hash,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            answer,
+            crate::approval::Answer::Done("Approved Show - 01".to_string())
+        );
+        add_mock.assert_async().await;
+        assert!(db.contains_key(&info_hash).unwrap());
+        assert!(Approval::get(&db, &info_hash).unwrap().unwrap().added);
+
+        crate::approval::handle(
+            &db,
+            &cfg,
+            &Default::default(),
+            &notifiers,
+            crate::approval::Action::Remove,
+            &info_hash,
+        )
+        .await
+        .unwrap();
+        remove_mock.assert_async().await;
+        assert_eq!(Approval::get(&db, &info_hash).unwrap(), None);
+        // the torrent isn't added again by the next poll
+        assert!(db.contains_key(&info_hash).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_not_modified() {
+        let server = MockServer::start_async().await;
+        let modified = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/rss.xml").matches(|req| {
+                    !req.headers
+                        .iter()
+                        .flatten()
+                        .any(|(name, _)| name.eq_ignore_ascii_case("if-none-match"))
+                });
+                then.status(200)
+                    .header("ETag", "\"v1\"")
+                    .body(test_feed(&server.url("")));
+            })
+            .await;
+        let not_modified = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path("/rss.xml")
+                    .header("If-None-Match", "\"v1\"");
+                then.status(304);
+            })
+            .await;
+        let torrent_mock = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/1.torrent");
+                then.status(200).body(TEST_TORRENT);
+            })
+            .await;
+        let info_hash = Torrent::read_from_bytes(TEST_TORRENT).unwrap().info_hash();
+        server
+            .mock_async(|when, then| {
+                when.method(POST).path("/transmission/rpc");
+                then.status(200).json_body(serde_json::json!({
+                    "arguments": {
+                        "torrent-added": { "hashString": info_hash, "id": 1, "name": "test" }
+                    },
+                    "result": "success"
+                }));
+            })
+            .await;
+
+        let cfg = test_config(&server.url(""));
+        let feed = cfg.rss_list[0].clone();
+        let db = Arc::new(sled::Config::new().temporary(true).open().unwrap());
+        for expected in [1, 0] {
+            let count = process_feed(
+                db.clone(),
+                feed.clone(),
+                cfg.clone(),
+                &Default::default(),
+                Default::default(),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+            assert_eq!(count, expected);
+        }
+        modified.assert_async().await;
+        not_modified.assert_async().await;
+        torrent_mock.assert_async().await;
+
+        // a dry run always looks at the full feed
+        dry_run_feed(
+            db.clone(),
+            feed.clone(),
+            cfg.clone(),
+            &Default::default(),
+            Default::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(modified.hits_async().await, 2);
+        // so does the next run once the filters changed
+        let mut feed = feed;
+        feed.filters.include = vec!["Show".to_string()];
+        process_feed(
+            db,
+            feed,
+            cfg,
+            &Default::default(),
+            Default::default(),
+            Default::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(modified.hits_async().await, 3);
+        assert_eq!(not_modified.hits_async().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_feed() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/rss.xml");
+                then.status(200).body(test_feed(&server.url("")));
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/1.torrent");
+                then.status(200).body(TEST_TORRENT);
+            })
+            .await;
+        let rpc_mock = server
+            .mock_async(|when, then| {
+                when.path("/transmission/rpc");
+                then.status(500);
+            })
+            .await;
+
+        let cfg = test_config(&server.url(""));
+        let db = Arc::new(sled::Config::new().temporary(true).open().unwrap());
+
+        let titles = dry_run_feed(
+            db.clone(),
+            cfg.rss_list[0].clone(),
+            cfg.clone(),
+            &Default::default(),
+            Default::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(titles, vec!["Show - 01".to_string()]);
+        assert_eq!(rpc_mock.hits_async().await, 0);
+        assert!(db.is_empty());
+        assert!(db.open_tree(ITEMS_TREE).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_atom_feed() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/atom.xml");
+                then.status(200).body(
+                    r#"<feed xmlns="http://www.w3.org/2005/Atom">
+                    <title>releases</title><id>urn:feed</id><updated>2024-01-01T00:00:00Z</updated>
+                    <entry><title>Distro 1.0</title><id>urn:1</id><updated>2024-01-01T00:00:00Z</updated>
+                    <link rel="enclosure" href="magnet:?xt=urn:btih:d70db7716583224da1684de8fa324822461917aa"/>
+                    </entry></feed>"#,
+                );
+            })
+            .await;
+        let db = Arc::new(sled::Config::new().temporary(true).open().unwrap());
+        let cfg = test_config(&server.url(""));
+        let mut feed = cfg.rss_list[0].clone();
+        feed.url = server.url("/atom.xml");
+
+        let titles = dry_run_feed(db, feed, cfg, &Default::default(), Default::default())
+            .await
+            .unwrap();
+        assert_eq!(titles, vec!["Distro 1.0".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_http_options() {
+        let server = MockServer::start_async().await;
+        let feed_mock = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path("/rss.xml")
+                    .header("Cookie", "uid=1; pass=abc")
+                    .header("User-Agent", "test-agent");
+                then.status(200).body(test_feed(&server.url("")));
+            })
+            .await;
+        let torrent_mock = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path("/1.torrent")
+                    .header("Cookie", "uid=1; pass=abc")
+                    .header("User-Agent", "test-agent");
+                then.status(200).body(TEST_TORRENT);
+            })
+            .await;
+        let db = Arc::new(sled::Config::new().temporary(true).open().unwrap());
+        let cfg: Config = toml::from_str(&format!(
+            r#"
+            [persistence]
+            path = "./test"
+            [transmission]
+            url = "{0}/transmission/rpc"
+            username = "user"
+            password = "pass"
+            [notification]
+            [[rss_list]]
+            title = "test"
+            url = "{0}/rss.xml"
+            download_dir = "/downloads"
+            cookies = "uid=1; pass=abc"
+            user_agent = "test-agent"
+            timeout = 10
+            "#,
+            server.url("")
+        ))
+        .unwrap();
+
+        let titles = dry_run_feed(
+            db,
+            cfg.rss_list[0].clone(),
+            cfg.clone(),
+            &Default::default(),
+            Default::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(titles, vec!["Show - 01".to_string()]);
+        feed_mock.assert_async().await;
+        torrent_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_feed_errors() {
+        let server = MockServer::start_async().await;
+        let unavailable = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/rss.xml");
+                then.status(503);
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/broken.xml");
+                then.status(200).body("not a feed");
+            })
+            .await;
+        let db = Arc::new(sled::Config::new().temporary(true).open().unwrap());
+        let cfg = test_config(&server.url(""));
+        let mut feed = cfg.rss_list[0].clone();
+
+        let err = dry_run_feed(
+            db.clone(),
+            feed.clone(),
+            cfg.clone(),
+            &Default::default(),
+            Default::default(),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, Error::FeedFetch { .. }), "{:?}", err);
+        assert_eq!(
+            unavailable.hits_async().await,
+            cfg.retry.max_attempts as usize
+        );
+        // the policy of the feed replaces the global one
+        feed.retry = Some(RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        });
+        dry_run_feed(
+            db.clone(),
+            feed.clone(),
+            cfg.clone(),
+            &Default::default(),
+            Default::default(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            unavailable.hits_async().await,
+            cfg.retry.max_attempts as usize + 1
+        );
+        feed.url = server.url("/broken.xml");
+        let err = dry_run_feed(db, feed, cfg, &Default::default(), Default::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::FeedParse { .. }), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn test_rpc_auth_error() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST).path("/transmission/rpc");
+                then.status(401).body("<h1>401: Unauthorized</h1>");
+            })
+            .await;
+        let cfg = test_config(&server.url(""));
+
+        let err = get_client(&cfg)
+            .unwrap()
+            .torrent_get(None, None)
+            .await
+            .unwrap_err();
+        let err = rpc_error(&cfg, err);
+        assert!(matches!(err, Error::TransmissionAuth { .. }), "{:?}", err);
+        // classified without asking transmission again
+        assert_eq!(mock.hits_async().await, 1);
+
+        // nothing listening
+        let cfg = test_config("http://127.0.0.1:1");
+        let err = get_client(&cfg)
+            .unwrap()
+            .torrent_get(None, None)
+            .await
+            .unwrap_err();
+        let err = rpc_error(&cfg, err);
+        assert!(matches!(err, Error::Transmission(_)), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn test_rpc_add_retry() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/rss.xml");
+                then.status(200).body(test_feed(&server.url("")));
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/1.torrent");
+                then.status(200).body(TEST_TORRENT);
+            })
+            .await;
+        let rpc_mock = server
+            .mock_async(|when, then| {
+                when.method(POST).path("/transmission/rpc");
+                then.status(200).json_body(serde_json::json!({
+                    "arguments": {
+                        "torrent-added": { "hashString": "", "id": 1, "name": "test" }
+                    },
+                    "result": "download directory not writable"
+                }));
+            })
+            .await;
+        let cfg = test_config(&server.url(""));
+        let db = Arc::new(sled::Config::new().temporary(true).open().unwrap());
+
+        let err = process_feed(
+            db,
+            cfg.rss_list[0].clone(),
+            cfg.clone(),
+            &Default::default(),
+            Default::default(),
+            Default::default(),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, Error::TorrentAdd { .. }), "{:?}", err);
+        assert_eq!(rpc_mock.hits_async().await, cfg.retry.max_attempts as usize);
+    }
+
+    #[tokio::test]
+    async fn test_add_args() {
+        let torrent = Torrent::read_from_bytes(TEST_TORRENT).unwrap();
+        let it = TorrentItem {
+            title: "test".to_string(),
+            info_hash: torrent.info_hash(),
+            source: TorrentSource::File {
+                torrent: Box::new(torrent),
+                metainfo: TEST_TORRENT.to_vec(),
+                url: "http://localhost/1.torrent".to_string(),
+            },
+            key: None,
+            approval: None,
+            page: None,
+        };
+
+        let add = it
+            .add_args(AddMode::Metainfo, "/downloads".to_string())
+            .unwrap();
+        assert_eq!(add.metainfo, Some(base64::encode_block(TEST_TORRENT)));
+        assert_eq!(add.filename, None);
+        let add = it.add_args(AddMode::Url, "/downloads".to_string()).unwrap();
+        assert_eq!(add.filename.as_deref(), Some("http://localhost/1.torrent"));
+        let add = it
+            .add_args(AddMode::Magnet, "/downloads".to_string())
+            .unwrap();
+        assert!(add.filename.unwrap().starts_with("magnet:?xt=urn:btih:"));
+        assert_eq!(add.download_dir.as_deref(), Some("/downloads"));
+    }
+
+    #[tokio::test]
+    async fn test_added_link() {
+        let link = "magnet:?xt=urn:btih:d70db7716583224da1684de8fa324822461917aa&dn=test";
+        let mut it = TorrentItem::new(
+            &reqwest::Client::new(),
+            &Default::default(),
+            link.to_string(),
+            "test".to_string(),
+        )
+        .await
+        .unwrap();
+        // without a page the notifications link to the torrent itself
+        assert_eq!(it.added("/downloads").link, link);
+
+        it.page = Some("https://example.com/show-01".to_string());
+        assert_eq!(it.added("/downloads").link, "https://example.com/show-01");
+        assert_eq!(it.download_link(), link);
+    }
+
+    #[tokio::test]
+    async fn test_magnet_item() {
+        let link = "magnet:?xt=urn:btih:d70db7716583224da1684de8fa324822461917aa&dn=test";
+        let it = TorrentItem::new(
+            &reqwest::Client::new(),
+            &RetryPolicy::default(),
+            link.to_string(),
+            "test".to_string(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(it.info_hash, "d70db7716583224da1684de8fa324822461917aa");
+        assert_eq!(it.magnet_link().unwrap(), link);
+
+        let invalid = TorrentItem::new(
+            &reqwest::Client::new(),
+            &RetryPolicy::default(),
+            "magnet:?dn=test".to_string(),
+            "test".to_string(),
+        )
+        .await;
+        assert!(invalid.is_err());
+    }
 
     #[tokio::test]
     async fn test_get_metainfo() {
@@ -249,7 +1503,13 @@ mod test {
     #[tokio::test]
     async fn test_torrent_new() {
         let url = "https://dl.dmhy.org/2022/08/17/d70db7716583224da1684de8fa324822461917aa.torrent";
-        let torrent = TorrentItem::new(url.to_string(), "test".to_string()).await;
+        let torrent = TorrentItem::new(
+            &reqwest::Client::new(),
+            &RetryPolicy::default(),
+            url.to_string(),
+            "test".to_string(),
+        )
+        .await;
         torrent.unwrap();
     }
 
@@ -258,8 +1518,10 @@ mod test {
         print!("test_info_hash");
         let file = std::fs::read_to_string("config.toml").unwrap();
         let cfg = toml::from_str::<Config>(&file).unwrap();
-        let mut client = get_client(&cfg);
+        let mut client = get_client(&cfg).unwrap();
         let tor = TorrentItem::new(
+            &reqwest::Client::new(),
+            &cfg.retry,
             "https://dl.dmhy.org/2022/08/17/d70db7716583224da1684de8fa324822461917aa.torrent"
                 .to_string(),
             "test".to_string(),
@@ -268,17 +1530,17 @@ mod test {
         .unwrap();
 
         let add: TorrentAddArgs = TorrentAddArgs {
-            filename: Some(tor.torrent.magnet_link().unwrap()),
+            filename: Some(tor.magnet_link().unwrap()),
             ..TorrentAddArgs::default()
         };
 
         let res: RpcResponse<TorrentAddedOrDuplicate> =
             tokio_test::block_on(client.torrent_add(add)).unwrap();
         if let TorrentAddedOrDuplicate::TorrentAdded(torrent) = res.arguments {
-            assert!(tor.torrent.info_hash() == torrent.clone().hash_string.unwrap());
+            assert!(tor.info_hash == torrent.clone().hash_string.unwrap());
             println!(
                 "hash match: {:?} == {:?}",
-                tor.torrent.info_hash(),
+                tor.info_hash,
                 torrent.clone().hash_string.unwrap()
             );
             _ = tokio_test::block_on(client.torrent_remove(vec![torrent.id().unwrap()], true));